@@ -0,0 +1,101 @@
+/* C API for the Chip-8 interpreter, see src/capi.rs. Generated by cbindgen, do not edit. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define CHIP8_WIDTH 64
+
+#define CHIP8_HEIGHT 32
+
+#define CHIP8_MAX_ROM_SIZE (4096 - 512)
+
+// A Chip-8 machine, created with chip8_new and freed with chip8_free.
+typedef struct Chip8Machine Chip8Machine;
+
+// Creates a machine with the default quirks and font and empty memory.
+struct Chip8Machine *chip8_new(void);
+
+// # Safety
+// `machine` must come from chip8_new and not be used afterwards. NULL is
+// ignored.
+void chip8_free(struct Chip8Machine *machine);
+
+// Resets the machine and loads `size` bytes at 0x200. Returns false, leaving
+// the machine as it was, if the ROM is empty or doesn't fit in memory.
+//
+// # Safety
+// `machine` must be valid and `data` must point to `size` readable bytes.
+bool chip8_load_rom(struct Chip8Machine *machine, const uint8_t *data, size_t size);
+
+// Back to the power on state, clearing memory including the ROM.
+//
+// # Safety
+// `machine` must be valid.
+void chip8_reset(struct Chip8Machine *machine);
+
+// Why the machine stopped running, NULL while it runs. Valid until the
+// machine is reset or freed, or has a ROM or state loaded.
+//
+// # Safety
+// `machine` must be valid.
+const char *chip8_last_error(const struct Chip8Machine *machine);
+
+// Runs one frame of instructions and ticks the timers once.
+//
+// # Safety
+// `machine` must be valid.
+bool chip8_run_frame(struct Chip8Machine *machine);
+
+// Runs a single instruction. The timers tick once every frame's worth of
+// instructions, 8 at the default speed, so stepping through a frame ticks
+// them as often as chip8_run_frame does.
+//
+// # Safety
+// `machine` must be valid.
+bool chip8_step(struct Chip8Machine *machine);
+
+// The screen as CHIP8_HEIGHT rows of 64 bits, where bit x of a row is the
+// pixel in column x. Valid until the machine is freed.
+//
+// # Safety
+// `machine` must be valid.
+const uint64_t *chip8_framebuffer(const struct Chip8Machine *machine);
+
+// Whether the pixel at (x, y) is lit, false outside the screen.
+//
+// # Safety
+// `machine` must be valid.
+bool chip8_pixel(const struct Chip8Machine *machine, size_t x, size_t y);
+
+// Presses or releases keypad key 0x0 to 0xF, other keys are ignored.
+//
+// # Safety
+// `machine` must be valid.
+void chip8_set_key(struct Chip8Machine *machine, uint8_t key, bool pressed);
+
+// Whether the beeper is sounding, that is the sound timer is running.
+//
+// # Safety
+// `machine` must be valid.
+bool chip8_sound_active(const struct Chip8Machine *machine);
+
+// Bytes needed by chip8_save_state.
+size_t chip8_state_size(void);
+
+// Writes the machine state to `data`, false if `size` is too small.
+//
+// # Safety
+// `machine` must be valid and `data` must point to `size` writable bytes.
+bool chip8_save_state(const struct Chip8Machine *machine, uint8_t *data, size_t size);
+
+// Restores a state written by chip8_save_state, false if it isn't one.
+//
+// # Safety
+// `machine` must be valid and `data` must point to `size` readable bytes.
+bool chip8_load_state(struct Chip8Machine *machine, const uint8_t *data, size_t size);
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,121 @@
+// Static analysis of ROMs, following the control flow from the entry point
+// without running anything.
+
+use crate::opcode;
+use crate::platform::Platform;
+use std::collections::BTreeMap;
+
+// where ROMs are loaded and start executing
+pub const ENTRY_POINT: usize = 0x200;
+
+// Reachable instructions, by address. Skips are assumed to go either way, and
+// BNNN jumps are not followed as their target depends on a register.
+pub fn reachable(rom: &[u8]) -> BTreeMap<usize, u16> {
+    let mut found = BTreeMap::new();
+    let mut pending = vec![ENTRY_POINT];
+
+    while let Some(addr) = pending.pop() {
+        if found.contains_key(&addr) {
+            continue;
+        }
+        let op = match read_op(rom, addr) {
+            Some(op) => op,
+            None => continue,
+        };
+        found.insert(addr, op);
+
+        pending.extend(successors(rom, addr, op));
+    }
+
+    found
+}
+
+// opcode at a memory address, if the ROM covers it
+pub fn read_op(rom: &[u8], addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(ENTRY_POINT)?;
+    let bytes = rom.get(offset..offset + 2)?;
+
+    Some(((bytes[0] as u16) << 8) + bytes[1] as u16)
+}
+
+// XO-CHIP F000 NNNN is the only instruction longer than 2 bytes
+fn size(op: u16) -> usize {
+    if op == 0xF000 {
+        4
+    } else {
+        2
+    }
+}
+
+fn is_skip(op: u16) -> bool {
+    match op >> 12 {
+        0x3 | 0x4 => true,
+        0x5 | 0x9 => op & 0xF == 0,
+        0xE => op & 0xFF == 0x9E || op & 0xFF == 0xA1,
+        _ => false,
+    }
+}
+
+// addresses execution can continue at after the instruction at `addr`
+pub fn successors(rom: &[u8], addr: usize, op: u16) -> Vec<usize> {
+    let next = addr + size(op);
+
+    match op >> 12 {
+        // return, or SCHIP exit
+        0x0 if op == 0x00EE || op == 0x00FD => vec![],
+        0x1 => vec![(op & 0xFFF) as usize],
+        // the callee is followed, and the return lands after the call
+        0x2 => vec![(op & 0xFFF) as usize, next],
+        0xB => vec![],
+        _ if is_skip(op) => {
+            let skipped = read_op(rom, next).map_or(2, size);
+            vec![next, next + skipped]
+        }
+        _ => vec![next],
+    }
+}
+
+// Guesses the platform a ROM was written for from the opcodes only some
+// platforms have. None means nothing platform specific was found.
+pub fn detect_platform(rom: &[u8]) -> Option<Platform> {
+    let platforms: Vec<Platform> = reachable(rom)
+        .values()
+        .filter_map(|&op| opcode::platform(op))
+        .collect();
+
+    // XO-CHIP is a superset of SCHIP, which doesn't run VIP machine code
+    [
+        Platform::Xochip,
+        Platform::Superchip,
+        Platform::OriginalChip8,
+    ]
+    .into_iter()
+    .find(|platform| platforms.contains(platform))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_platform_specific_opcodes_that_are_reached() {
+        // hires, then loop
+        assert_eq!(
+            detect_platform(&[0x00, 0xFF, 0x12, 0x02]),
+            Some(Platform::Superchip)
+        );
+        // call machine code at 0x123, then loop
+        assert_eq!(
+            detect_platform(&[0x01, 0x23, 0x12, 0x02]),
+            Some(Platform::OriginalChip8)
+        );
+        // the same call after the loop is data
+        assert_eq!(detect_platform(&[0x12, 0x00, 0x01, 0x23]), None);
+    }
+
+    #[test]
+    fn zeroed_memory_is_not_a_machine_code_call() {
+        // skip over a zero word, as ROMs do to keep data inline
+        assert_eq!(detect_platform(&[0x30, 0x00, 0x00, 0x00, 0x12, 0x04]), None);
+    }
+}
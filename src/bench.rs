@@ -0,0 +1,74 @@
+// Interpreter throughput, measured by running small ROMs that each stress one
+// kind of work on headless devices.
+
+use crate::headless::HeadlessChip8;
+use std::time::Instant;
+
+pub struct Benchmark {
+    pub name: &'static str,
+    rom: &'static [u8],
+}
+
+pub const BENCHMARKS: [Benchmark; 3] = [
+    // arithmetic and logic between registers
+    Benchmark {
+        name: "alu",
+        rom: &[
+            0x70, 0x01, // 200: v0 += 1
+            0x81, 0x04, // 202: v1 += v0
+            0x82, 0x15, // 204: v2 -= v1
+            0x83, 0x21, // 206: v3 |= v2
+            0x84, 0x32, // 208: v4 &= v3
+            0x85, 0x43, // 20a: v5 ^= v4
+            0x86, 0x56, // 20c: v6 = v5 >> 1
+            0x87, 0x6E, // 20e: v7 = v6 << 1
+            0x30, 0x00, // 210: skip if v0 == 0
+            0x12, 0x00, // 212: jump 200
+            0x12, 0x00, // 214: jump 200
+        ],
+    },
+    // a sprite drawn across the screen
+    Benchmark {
+        name: "sprites",
+        rom: &[
+            0xA2, 0x0C, // 200: i = 20c
+            0xD0, 0x1F, // 202: draw 15 rows at v0, v1
+            0x70, 0x03, // 204: v0 += 3
+            0x71, 0x01, // 206: v1 += 1
+            0x12, 0x00, // 208: jump 200
+            0x00, 0x00, // 20a: padding
+            0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF, // 20c: sprite
+            0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81,
+        ],
+    },
+    // blocks of memory copied through the registers
+    Benchmark {
+        name: "memcpy",
+        rom: &[
+            0xA3, 0x00, // 200: i = 300
+            0xFF, 0x65, // 202: load v0 to vf
+            0xA4, 0x00, // 204: i = 400
+            0xFF, 0x55, // 206: store v0 to vf
+            0xA3, 0x10, // 208: i = 310
+            0xFF, 0x65, // 20a: load v0 to vf
+            0xA4, 0x10, // 20c: i = 410
+            0xFF, 0x55, // 20e: store v0 to vf
+            0x12, 0x00, // 210: jump 200
+        ],
+    },
+];
+
+impl Benchmark {
+    // instructions per second over `instructions` instructions
+    pub fn run(&self, instructions: usize) -> f64 {
+        let mut c8 = HeadlessChip8::headless();
+        c8.load(self.rom);
+
+        let start = Instant::now();
+        for _ in 0..instructions {
+            c8.execute();
+        }
+
+        instructions as f64 / start.elapsed().as_secs_f64()
+    }
+}
@@ -0,0 +1,214 @@
+// C API over the headless machine, for hosts that aren't written in Rust.
+// The header is include/chip8.h, regenerated with
+// `cbindgen --config cbindgen.toml --output include/chip8.h src/capi.rs`.
+//
+// A machine that hits a bad opcode stops running instead of unwinding into
+// the host, and chip8_run_frame and chip8_step return false from then on
+// until a ROM is loaded, a state restored or the machine reset. Nothing is
+// printed, chip8_last_error says what went wrong.
+
+use crate::chip_8::{Key, Snapshot};
+use crate::crash;
+use crate::headless::HeadlessChip8;
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::slice;
+
+pub const CHIP8_WIDTH: usize = 64;
+pub const CHIP8_HEIGHT: usize = 32;
+pub const CHIP8_MAX_ROM_SIZE: usize = 0x1000 - 0x200;
+
+/// A Chip-8 machine, created with chip8_new and freed with chip8_free.
+pub struct Chip8Machine {
+    c8: HeadlessChip8,
+    // why the machine stopped, None while it runs
+    error: Option<CString>,
+}
+
+impl Chip8Machine {
+    fn run(&mut self, f: impl FnOnce(&mut HeadlessChip8)) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+
+        let c8 = &mut self.c8;
+        if let Err(message) = crash::catch(|| f(c8)) {
+            self.error = Some(CString::new(message.replace('\0', "")).unwrap());
+        }
+        self.error.is_none()
+    }
+}
+
+/// Creates a machine with the default quirks and font and empty memory.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8Machine {
+    Box::into_raw(Box::new(Chip8Machine {
+        c8: HeadlessChip8::headless(),
+        error: None,
+    }))
+}
+
+/// # Safety
+/// `machine` must come from chip8_new and not be used afterwards. NULL is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(machine: *mut Chip8Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// Resets the machine and loads `size` bytes at 0x200. Returns false, leaving
+/// the machine as it was, if the ROM is empty or doesn't fit in memory.
+///
+/// # Safety
+/// `machine` must be valid and `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    machine: *mut Chip8Machine,
+    data: *const u8,
+    size: usize,
+) -> bool {
+    let machine = &mut *machine;
+    if data.is_null() || size == 0 || size > CHIP8_MAX_ROM_SIZE {
+        return false;
+    }
+
+    machine.c8.reset();
+    machine.c8.load(slice::from_raw_parts(data, size));
+    machine.error = None;
+    true
+}
+
+/// Back to the power on state, clearing memory including the ROM.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(machine: *mut Chip8Machine) {
+    let machine = &mut *machine;
+    machine.c8.reset();
+    machine.error = None;
+}
+
+/// Why the machine stopped running, NULL while it runs. Valid until the
+/// machine is reset or freed, or has a ROM or state loaded.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(machine: *const Chip8Machine) -> *const c_char {
+    match &(*machine).error {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Runs one frame of instructions and ticks the timers once.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(machine: *mut Chip8Machine) -> bool {
+    (*machine).run(|c8| c8.step_frame())
+}
+
+/// Runs a single instruction. The timers tick once every frame's worth of
+/// instructions, 8 at the default speed, so stepping through a frame ticks
+/// them as often as chip8_run_frame does.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(machine: *mut Chip8Machine) -> bool {
+    (*machine).run(|c8| c8.step_instruction())
+}
+
+/// The screen as CHIP8_HEIGHT rows of 64 bits, where bit x of a row is the
+/// pixel in column x. Valid until the machine is freed.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(machine: *const Chip8Machine) -> *const u64 {
+    (*machine).c8.framebuffer().as_ptr()
+}
+
+/// Whether the pixel at (x, y) is lit, false outside the screen.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_pixel(machine: *const Chip8Machine, x: usize, y: usize) -> bool {
+    x < CHIP8_WIDTH && y < CHIP8_HEIGHT && (*machine).c8.framebuffer()[y] & (1 << x) != 0
+}
+
+/// Presses or releases keypad key 0x0 to 0xF, other keys are ignored.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(machine: *mut Chip8Machine, key: u8, pressed: bool) {
+    if key <= 0xF {
+        (*machine).c8.set_key(Key::new(key), pressed);
+    }
+}
+
+/// Whether the beeper is sounding, that is the sound timer is running.
+///
+/// # Safety
+/// `machine` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(machine: *const Chip8Machine) -> bool {
+    (*machine).c8.sound_active()
+}
+
+/// Bytes needed by chip8_save_state.
+#[no_mangle]
+pub extern "C" fn chip8_state_size() -> usize {
+    Snapshot::SIZE
+}
+
+/// Writes the machine state to `data`, false if `size` is too small.
+///
+/// # Safety
+/// `machine` must be valid and `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    machine: *const Chip8Machine,
+    data: *mut u8,
+    size: usize,
+) -> bool {
+    if data.is_null() || size < Snapshot::SIZE {
+        return false;
+    }
+
+    let bytes = (*machine).c8.snapshot().to_bytes();
+    slice::from_raw_parts_mut(data, bytes.len()).copy_from_slice(&bytes);
+    true
+}
+
+/// Restores a state written by chip8_save_state, false if it isn't one.
+///
+/// # Safety
+/// `machine` must be valid and `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    machine: *mut Chip8Machine,
+    data: *const u8,
+    size: usize,
+) -> bool {
+    if data.is_null() || size < Snapshot::SIZE {
+        return false;
+    }
+
+    match Snapshot::from_bytes(slice::from_raw_parts(data, Snapshot::SIZE)) {
+        Some(snapshot) => {
+            let machine = &mut *machine;
+            machine.c8.restore(&snapshot);
+            machine.error = None;
+            true
+        }
+        None => false,
+    }
+}
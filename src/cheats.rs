@@ -0,0 +1,307 @@
+// Cheat search and frozen memory.
+//
+// A search starts from a snapshot of ram with every address in the program
+// area as a candidate. Each filter compares the current ram to the previous
+// snapshot and keeps only the addresses that match, which quickly narrows
+// things down to counters like lives or score.
+
+use crate::chip_8::{Chip8, DisplayInterface, KeyboardInterface, SoundInterface};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// the interpreter area below this is never searched
+const PROGRAM_START: usize = 0x200;
+
+pub enum Filter {
+    Equal(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+}
+
+#[derive(Default)]
+pub struct CheatSearch {
+    previous: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl CheatSearch {
+    pub fn new() -> Self {
+        CheatSearch::default()
+    }
+
+    pub fn start(&mut self, ram: &[u8]) {
+        self.previous = ram.to_vec();
+        self.candidates = (PROGRAM_START..ram.len()).collect();
+    }
+
+    // returns the number of candidates left, or None if no search was started
+    pub fn filter(&mut self, ram: &[u8], filter: Filter) -> Option<usize> {
+        if self.previous.is_empty() {
+            return None;
+        }
+
+        let previous = &self.previous;
+        self.candidates.retain(|&addr| {
+            let (old, new) = (previous[addr], ram[addr]);
+            match filter {
+                Filter::Equal(n) => new == n,
+                Filter::Increased => new > old,
+                Filter::Decreased => new < old,
+                Filter::Unchanged => new == old,
+            }
+        });
+        self.previous = ram.to_vec();
+
+        Some(self.candidates.len())
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    // written once into the ROM as it is loaded
+    Patch,
+    // written every frame
+    Freeze,
+}
+
+struct Cheat {
+    name: String,
+    addr: usize,
+    value: u8,
+    kind: Kind,
+    // byte the ROM had before patching, written back when cheats are disabled
+    original: u8,
+}
+
+// Cheats from a cheat file, plus memory frozen from the debugger.
+//
+// A cheat file has one entry per line, blank lines and lines starting with
+// '#' are ignored. Addresses and values are hex, the rest of the line is the
+// name of the cheat:
+//
+//   patch  2a4 12  skip the title screen
+//   freeze 3f0 03  infinite lives
+#[derive(Default)]
+pub struct Cheats {
+    entries: Vec<Cheat>,
+    disabled: bool,
+    frozen: BTreeMap<usize, u8>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Cheats::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut cheats = Cheats::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let cheat = Cheats::parse(line)
+                .ok_or_else(|| format!("{}:{}: bad cheat '{}'", path.display(), n + 1, line))?;
+            cheats.entries.push(cheat);
+        }
+
+        Ok(cheats)
+    }
+
+    fn parse(line: &str) -> Option<Cheat> {
+        let (kind, rest) = next_word(line)?;
+        let (addr, rest) = next_word(rest)?;
+        let (value, name) = next_word(rest)?;
+
+        let kind = match kind {
+            "patch" => Kind::Patch,
+            "freeze" => Kind::Freeze,
+            _ => return None,
+        };
+        let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16).ok()?;
+        let value = u8::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+        let name = name.trim().to_string();
+
+        if !(PROGRAM_START..0x1000).contains(&addr) {
+            return None;
+        }
+
+        Some(Cheat {
+            name,
+            addr,
+            value,
+            kind,
+            original: 0,
+        })
+    }
+
+    // applies the patch entries to ROM bytes about to be passed to Chip8::load
+    pub fn patch_rom(&mut self, rom: &mut Vec<u8>) {
+        for cheat in self.entries.iter_mut().filter(|c| c.kind == Kind::Patch) {
+            let offset = cheat.addr - PROGRAM_START;
+            if rom.len() <= offset {
+                rom.resize(offset + 1, 0);
+            }
+
+            cheat.original = rom[offset];
+            if !self.disabled {
+                rom[offset] = cheat.value;
+            }
+        }
+    }
+
+    // turns the cheat file entries on or off, returns whether they are now on
+    pub fn toggle<K, D, S>(&mut self, c8: &mut Chip8<K, D, S>) -> bool
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        self.disabled = !self.disabled;
+
+        for cheat in self.entries.iter().filter(|c| c.kind == Kind::Patch) {
+            let value = if self.disabled {
+                cheat.original
+            } else {
+                cheat.value
+            };
+            c8.poke(cheat.addr, value);
+        }
+
+        !self.disabled
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|cheat| cheat.name.as_str())
+    }
+
+    pub fn freeze(&mut self, addr: usize, value: u8) {
+        self.frozen.insert(addr, value);
+    }
+
+    pub fn unfreeze(&mut self, addr: usize) {
+        self.frozen.remove(&addr);
+    }
+
+    pub fn frozen(&self) -> &BTreeMap<usize, u8> {
+        &self.frozen
+    }
+
+    // writes frozen memory, call once per frame
+    pub fn apply<K, D, S>(&self, c8: &mut Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        if !self.disabled {
+            for cheat in self.entries.iter().filter(|c| c.kind == Kind::Freeze) {
+                c8.poke(cheat.addr, cheat.value);
+            }
+        }
+
+        for (&addr, &value) in &self.frozen {
+            c8.poke(addr, value);
+        }
+    }
+}
+
+// splits off the first word, returns it and the rest of the line
+fn next_word(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+
+    Some(s.split_at(s.find(char::is_whitespace).unwrap_or(s.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessChip8;
+
+    #[test]
+    fn parses_cheat_lines() {
+        let cheat = Cheats::parse("patch 2a4 12  skip the title screen").unwrap();
+        assert!(cheat.kind == Kind::Patch);
+        assert_eq!((cheat.addr, cheat.value), (0x2A4, 0x12));
+        assert_eq!(cheat.name, "skip the title screen");
+
+        let cheat = Cheats::parse("freeze\t0x3F0 0x03").unwrap();
+        assert!(cheat.kind == Kind::Freeze);
+        assert_eq!((cheat.addr, cheat.value), (0x3F0, 0x03));
+        assert_eq!(cheat.name, "");
+    }
+
+    #[test]
+    fn rejects_bad_cheat_lines() {
+        for line in [
+            "poke 2a4 12",
+            "patch 2a4",
+            "patch zz 12",
+            "patch 2a4 100",
+            "patch 1ff 12",
+            "freeze 1000 12",
+        ] {
+            assert!(Cheats::parse(line).is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn patches_apply_to_the_rom_and_toggle_off() {
+        let mut cheats = Cheats::new();
+        cheats.entries.push(Cheats::parse("patch 201 aa").unwrap());
+        cheats.entries.push(Cheats::parse("patch 204 bb").unwrap());
+        cheats.entries.push(Cheats::parse("freeze 300 cc").unwrap());
+
+        let mut rom = vec![0x12, 0x00];
+        cheats.patch_rom(&mut rom);
+        assert_eq!(rom, [0x12, 0xAA, 0x00, 0x00, 0xBB]);
+
+        let mut c8 = HeadlessChip8::headless();
+        c8.load(&rom);
+        cheats.apply(&mut c8);
+        assert_eq!(c8.ram()[0x300], 0xCC);
+
+        assert!(!cheats.toggle(&mut c8));
+        assert_eq!(c8.ram()[0x200..0x205], [0x12, 0x00, 0x00, 0x00, 0x00]);
+        assert!(cheats.toggle(&mut c8));
+        assert_eq!(c8.ram()[0x201], 0xAA);
+    }
+
+    #[test]
+    fn search_narrows_down_to_a_changing_counter() {
+        let mut ram = [0u8; 0x1000];
+        ram[0x1FF] = 3;
+        ram[0x310] = 3;
+        ram[0x320] = 3;
+        ram[0x330] = 9;
+
+        let mut search = CheatSearch::new();
+        assert_eq!(search.filter(&ram, Filter::Unchanged), None);
+        search.start(&ram);
+
+        // the interpreter area is never a candidate
+        assert_eq!(search.filter(&ram, Filter::Equal(3)), Some(2));
+
+        ram[0x310] = 2;
+        ram[0x320] = 4;
+        assert_eq!(search.filter(&ram, Filter::Decreased), Some(1));
+        assert_eq!(search.candidates(), [0x310]);
+
+        assert_eq!(search.filter(&ram, Filter::Unchanged), Some(1));
+        ram[0x310] = 5;
+        assert_eq!(search.filter(&ram, Filter::Increased), Some(1));
+        assert_eq!(search.filter(&ram, Filter::Equal(4)), Some(0));
+    }
+}
@@ -1,29 +1,19 @@
 use crate::console;
-use rand;
-use std::thread;
-use std::time;
+use crate::font::{Font, FontData, FONT_SIZE};
+use crate::opcode;
 
-const SPRITE_POINTER: usize = 0x20;
+// default speed, instructions executed per frame
+const CYCLES_PER_FRAME: usize = 8;
+
+// The COSMAC VIP runs about 3668 machine cycles per frame, of which the
+// display DMA and interrupt routine take about 1100
+const VIP_CYCLES_PER_FRAME: usize = 3668 - 1100;
+
+// where the font is placed unless told otherwise, right above the stack
+pub const FONT_BASE: usize = 0x20;
 const SPRITE_SIZE: usize = 5;
-const SPRITE_DATA: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Key {
     value: u8,
 }
@@ -42,6 +32,41 @@ impl Key {
     }
 }
 
+// Behaviors that differ between Chip-8 platforms. The names follow the
+// community CHIP-8 database, all false is a modern Chip-8 interpreter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift v[x] in place instead of v[y]
+    pub shift: bool,
+    // FX55/FX65 add x to i instead of x + 1
+    pub memory_increment_by_x: bool,
+    // FX55/FX65 leave i unchanged
+    pub memory_leave_i_unchanged: bool,
+    // sprites wrap around the edges of the screen instead of clipping
+    pub wrap: bool,
+    // BNNN behaves as BXNN, jumping to XNN + v[x]
+    pub jump: bool,
+    // 8XY1/8XY2/8XY3 reset v[F] to 0
+    pub logic: bool,
+}
+
+impl Quirks {
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        let quirk = match name {
+            "shift" => &mut self.shift,
+            "memoryIncrementByX" => &mut self.memory_increment_by_x,
+            "memoryLeaveIUnchanged" => &mut self.memory_leave_i_unchanged,
+            "wrap" => &mut self.wrap,
+            "jump" => &mut self.jump,
+            "logic" => &mut self.logic,
+            _ => return Err(format!("unknown quirk '{}'", name)),
+        };
+        *quirk = value;
+
+        Ok(())
+    }
+}
+
 pub trait KeyboardInterface {
     fn is_key_pressed(&self, key: Key) -> bool;
     fn get_pressed_key(&self) -> Option<Key>;
@@ -52,6 +77,7 @@ pub trait KeyboardInterface {
 // y values are packed ascending, array[0] = y[0] and array[31] = y[31]
 pub type BitMap = [u64; 0x20];
 
+// called once per frame, and only when the screen changed
 pub trait DisplayInterface {
     fn draw(&mut self, bitmap: &BitMap);
 }
@@ -61,6 +87,111 @@ pub trait SoundInterface {
     fn stop(&self);
 }
 
+// The machine state apart from memory and the screen, cheap enough to take
+// before every instruction
+#[derive(Clone, Copy)]
+pub struct Registers {
+    pub cycle: usize,
+    pub frame: usize,
+    pub budget: usize,
+    pub v: [u8; 0x10],
+    pub i: usize,
+    pub dt: u8,
+    pub st: u8,
+    pub pc: usize,
+    pub sp: usize,
+}
+
+// Complete machine state, everything but the attached devices
+#[derive(Clone)]
+pub struct Snapshot {
+    pub cycle: usize,
+    pub frame: usize,
+    pub budget: usize,
+    pub v: [u8; 0x10],
+    pub i: usize,
+    pub dt: u8,
+    pub st: u8,
+    pub pc: usize,
+    pub sp: usize,
+    pub ram: [u8; 0x1000],
+    pub vram: BitMap,
+}
+
+impl Snapshot {
+    // size of a snapshot as bytes, numbers are stored little endian with
+    // usize as 8 bytes
+    pub const SIZE: usize = 8 * 6 + 0x10 + 2 + 0x1000 + 8 * 0x20;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Snapshot::SIZE);
+
+        for n in [
+            self.cycle,
+            self.frame,
+            self.budget,
+            self.i,
+            self.pc,
+            self.sp,
+        ] {
+            out.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&[self.dt, self.st]);
+        out.extend_from_slice(&self.ram);
+        for row in self.vram {
+            out.extend_from_slice(&row.to_le_bytes());
+        }
+
+        out
+    }
+
+    // None if `bytes` isn't a snapshot
+    pub fn from_bytes(bytes: &[u8]) -> Option<Snapshot> {
+        if bytes.len() != Snapshot::SIZE {
+            return None;
+        }
+
+        let mut pos = 0;
+        let mut take = |n: usize| {
+            let slice = &bytes[pos..pos + n];
+            pos += n;
+            slice
+        };
+        let mut number = || u64::from_le_bytes(take(8).try_into().unwrap()) as usize;
+        let (cycle, frame, budget, i, pc, sp) =
+            (number(), number(), number(), number(), number(), number());
+
+        let v = take(0x10).try_into().unwrap();
+        let timers = take(2);
+        let (dt, st) = (timers[0], timers[1]);
+        let ram = take(0x1000).try_into().unwrap();
+        let mut vram = [0; 0x20];
+        for row in vram.iter_mut() {
+            *row = u64::from_le_bytes(take(8).try_into().unwrap());
+        }
+
+        // reject states that would index out of memory
+        if pc >= 0x1000 || i >= 0x1000 || sp > 0x20 {
+            return None;
+        }
+
+        Some(Snapshot {
+            cycle,
+            frame,
+            budget,
+            v,
+            i,
+            dt,
+            st,
+            pc,
+            sp,
+            ram,
+            vram,
+        })
+    }
+}
+
 pub struct Chip8<K, D, S>
 where
     K: KeyboardInterface,
@@ -68,6 +199,10 @@ where
     S: SoundInterface,
 {
     cycle: usize,
+    // frame count and machine cycles left in it, with VIP timing
+    frame: usize,
+    budget: usize,
+    vip_timing: bool,
     v: [u8; 0x10],
     i: usize,
     dt: u8,
@@ -76,6 +211,14 @@ where
     sp: usize,
     ram: [u8; 0x1000],
     vram: BitMap,
+    // keys held down by the host through set_key, on top of the keyboard
+    keys: [bool; 0x10],
+    // vram changed since it was last drawn
+    dirty: bool,
+    quirks: Quirks,
+    cycles_per_frame: usize,
+    font: FontData,
+    font_base: usize,
     keyboard: K,
     display: D,
     sound: S,
@@ -90,6 +233,9 @@ where
     pub fn new(keyboard: K, display: D, sound: S) -> Self {
         let mut new_c8 = Chip8 {
             cycle: 0,
+            frame: 0,
+            budget: VIP_CYCLES_PER_FRAME,
+            vip_timing: false,
             v: [0x0; 0x10],
             i: 0x0,
             dt: 0x0,
@@ -98,35 +244,240 @@ where
             sp: 0x0,
             ram: [0x0; 0x1000],
             vram: [0x0; 0x20],
+            keys: [false; 0x10],
+            dirty: true,
+            quirks: Quirks::default(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+            font: *Font::default().data(),
+            font_base: FONT_BASE,
             keyboard,
             display,
             sound,
         };
 
+        new_c8.reset();
+
+        new_c8
+    }
+
+    // back to the power on state, quirks and speed are kept
+    pub fn reset(&mut self) {
+        self.ram = [0x0; 0x1000];
+
         // initialize sprite data to system memory
-        for (i, &byte) in SPRITE_DATA.iter().enumerate() {
-            new_c8.ram[SPRITE_POINTER + i] = byte;
-        }
+        self.ram[self.font_base..self.font_base + FONT_SIZE].copy_from_slice(&self.font);
+
+        self.soft_reset();
+    }
+
+    // clears registers, timers, the stack pointer and the screen, but leaves
+    // memory as it is
+    pub fn soft_reset(&mut self) {
+        self.cycle = 0;
+        self.frame = 0;
+        self.budget = VIP_CYCLES_PER_FRAME;
+        self.v = [0x0; 0x10];
+        self.i = 0x0;
+        self.dt = 0x0;
+        self.st = 0x0;
+        self.pc = 0x200;
+        self.sp = 0x0;
+        self.vram = [0x0; 0x20];
+        self.dirty = true;
 
         // draw initial blank state
-        new_c8.display.draw(&new_c8.vram);
+        self.sound.stop();
+        self.present();
+    }
 
-        new_c8
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // instructions executed per frame
+    pub fn set_speed(&mut self, cycles_per_frame: usize) {
+        self.cycles_per_frame = cycles_per_frame.max(1);
+    }
+
+    // charges each instruction its cost in COSMAC VIP machine cycles, and
+    // makes DXYN wait for the next frame, instead of a fixed number of
+    // instructions per frame
+    pub fn set_vip_timing(&mut self, enabled: bool) {
+        self.vip_timing = enabled;
+    }
+
+    // replaces the font, the base must leave room for it below 0x200
+    pub fn set_font(&mut self, font: &FontData, base: usize) {
+        self.ram[self.font_base..self.font_base + FONT_SIZE].fill(0);
+        self.font = *font;
+        self.font_base = base;
+        self.ram[base..base + FONT_SIZE].copy_from_slice(font);
     }
 
     pub fn load(&mut self, rom: &[u8]) {
-        for (i, &byte) in rom.iter().enumerate() {
-            self.ram[self.pc + i] = byte;
+        // anything past the end of memory is dropped
+        for (i, &byte) in rom.iter().take(0x1000 - 0x200).enumerate() {
+            self.ram[0x200 + i] = byte;
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cycle: self.cycle,
+            frame: self.frame,
+            budget: self.budget,
+            v: self.v,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            pc: self.pc,
+            sp: self.sp,
+            ram: self.ram,
+            vram: self.vram,
         }
     }
 
+    pub fn registers(&self) -> Registers {
+        Registers {
+            cycle: self.cycle,
+            frame: self.frame,
+            budget: self.budget,
+            v: self.v,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            pc: self.pc,
+            sp: self.sp,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cycle = snapshot.cycle;
+        self.frame = snapshot.frame;
+        self.budget = snapshot.budget;
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.ram = snapshot.ram;
+        self.vram = snapshot.vram;
+        self.dirty = true;
+
+        if self.st == 0 {
+            self.sound.stop();
+        } else {
+            self.sound.start();
+        }
+        self.present();
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn v(&self) -> &[u8; 0x10] {
+        &self.v
+    }
+
+    pub fn ram(&self) -> &[u8; 0x1000] {
+        &self.ram
+    }
+
+    pub fn poke(&mut self, addr: usize, byte: u8) {
+        self.ram[addr & 0xFFF] = byte;
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8; 0x1000] {
+        &mut self.ram
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc & 0xFFF;
+    }
+
+    pub fn set_i(&mut self, i: usize) {
+        self.i = i & 0xFFF;
+    }
+
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.v[x & 0xF] = value;
+    }
+
+    pub fn set_timers(&mut self, dt: u8, st: u8) {
+        self.dt = dt;
+        self.st = st;
+    }
+
+    // Host driven use, for frontends that don't implement the device traits.
+    // Run with null devices, these are all that's needed: step, draw the
+    // framebuffer, play a tone while sound_active and pass key presses on.
+
+    pub fn step_instruction(&mut self) {
+        self.execute();
+    }
+
+    // runs until the timers next tick, one 60th of a second of emulated time
+    pub fn step_frame(&mut self) {
+        let frame = self.frame();
+        while self.frame() == frame {
+            self.execute();
+        }
+    }
+
+    pub fn framebuffer(&self) -> &BitMap {
+        &self.vram
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        self.keys[*key.value() as usize] = pressed;
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.st > 0
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.keys[*key.value() as usize] || self.keyboard.is_key_pressed(key)
+    }
+
+    fn get_pressed_key(&self) -> Option<Key> {
+        let held = (0..0x10).find(|&key| self.keys[key as usize]);
+
+        held.map(Key::new)
+            .or_else(|| self.keyboard.get_pressed_key())
+    }
+
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn timers(&self) -> (u8, u8) {
+        (self.dt, self.st)
+    }
+
+    pub fn frame(&self) -> usize {
+        if self.vip_timing {
+            self.frame
+        } else {
+            self.cycle / self.cycles_per_frame
+        }
+    }
+
+    pub fn peek_op(&self) -> u16 {
+        ((self.ram[self.pc] as u16) << 8) + self.ram[(self.pc + 1) & 0xFFF] as u16
+    }
+
     fn fetch_op(&mut self) -> u16 {
-        let msb = self.ram[self.pc];
-        let lsb = self.ram[self.pc + 1];
+        let op = self.peek_op();
         self.pc += 2;
 
-        let op = ((msb as u16) << 8) + lsb as u16;
-        return op;
+        op
     }
 
     fn push_stack(&mut self, addr: usize) {
@@ -151,14 +502,20 @@ where
         let msb = self.ram[self.sp];
         let lsb = self.ram[self.sp + 1];
 
-        let addr = ((msb as usize) << 8) + lsb as usize;
-        return addr;
+        (((msb as usize) << 8) + lsb as usize) & 0xFFF
     }
 
     pub fn execute(&mut self) {
+        let start = self.pc;
+
         // fetch
         let opcode = self.fetch_op();
 
+        // the VIP draws sprites after waiting for the display interrupt
+        if self.vip_timing && opcode >> 12 == 0xD {
+            self.end_frame();
+        }
+
         // decode
         let addr = (opcode & 0xFFF) as usize;
         let x = ((opcode >> 8) & 0xF) as usize;
@@ -175,7 +532,7 @@ where
                         for row in self.vram.iter_mut() {
                             *row = 0;
                         }
-                        self.display.draw(&self.vram);
+                        self.dirty = true;
                     }
                     0x0EE => {
                         // return
@@ -185,7 +542,7 @@ where
 
                         self.pc = addr;
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
             0x1 => {
@@ -235,7 +592,7 @@ where
                             self.pc += 2;
                         }
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
             0x6 => {
@@ -270,6 +627,9 @@ where
                         });
 
                         self.v[x] |= self.v[y];
+                        if self.quirks.logic {
+                            self.v[0xF] = 0;
+                        }
                     }
                     0x2 => {
                         // v[x] &= v[y]
@@ -281,6 +641,9 @@ where
                         });
 
                         self.v[x] &= self.v[y];
+                        if self.quirks.logic {
+                            self.v[0xF] = 0;
+                        }
                     }
                     0x3 => {
                         // v[x] ^= v[y]
@@ -292,6 +655,9 @@ where
                         });
 
                         self.v[x] ^= self.v[y];
+                        if self.quirks.logic {
+                            self.v[0xF] = 0;
+                        }
                     }
                     0x4 => {
                         // v[x] += v[y]
@@ -318,6 +684,7 @@ where
                     0x6 => {
                         // set v[F] to value of v[y] & 1 (value of lsb)
                         // v[x] = v[y] >> 1
+                        // with the shift quirk v[x] is shifted instead of v[y]
                         console::debug(|| {
                             format!(
                             "{:x}: set v[{:x}] to value of v[{:x}] shifted right by 1 bit (div by 2)",
@@ -325,6 +692,7 @@ where
                         )
                         });
 
+                        let y = if self.quirks.shift { x } else { y };
                         self.v[0xF] = self.v[y] & 1;
                         self.v[x] = self.v[y] >> 1;
                     }
@@ -342,6 +710,7 @@ where
                     0xE => {
                         // set v[F] to v[y] >> 7 (value of msb)
                         // v[x] = v[y] << 1
+                        // with the shift quirk v[x] is shifted instead of v[y]
                         console::debug(|| {
                             format!(
                             "{:x}: set v[{:x}] to value of v[{:x}] shifted left by 1 bit (mult by 2)",
@@ -349,10 +718,11 @@ where
                         )
                         });
 
+                        let y = if self.quirks.shift { x } else { y };
                         self.v[0xF] = self.v[y] >> 7;
                         self.v[x] = self.v[y] << 1;
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
             0x9 => {
@@ -367,7 +737,7 @@ where
                             self.pc += 2;
                         }
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
             0xA => {
@@ -378,6 +748,14 @@ where
             }
             0xB => {
                 // jump to addr + v[0]
+                // with the jump quirk this is BXNN, jump to addr + v[x]
+                let offset = if self.quirks.jump {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                let addr = addr + offset as usize;
+
                 console::debug(|| format!("{:x}: jump to {:#05x}", opcode, addr));
 
                 if addr < 0x200 {
@@ -395,9 +773,10 @@ where
             }
             0xD => {
                 // display n-byte sprite on screen at point (x, y)
+                // the sprite clips at the screen edges, or wraps with the wrap quirk
                 let n = (val & 0xF) as usize;
-                let x = self.v[x] as usize;
-                let y = self.v[y] as usize;
+                let x = self.v[x] as usize % 64;
+                let y = self.v[y] as usize % 32;
 
                 console::debug(|| {
                     format!(
@@ -409,19 +788,28 @@ where
                 self.v[0xF] = 0;
 
                 for row in 0..n {
-                    let byte = self.ram[self.i + row];
+                    let byte = self.ram[(self.i + row) & 0xFFF];
                     let sprite_row = byte.reverse_bits() as u64;
 
-                    let previous = self.vram[y + row] & 0xFF << x;
-
-                    self.vram[y + row] ^= sprite_row << x;
-
-                    if self.vram[y + row] & previous < previous {
+                    let screen_row = match (y + row, self.quirks.wrap) {
+                        (screen_row, _) if screen_row < 32 => screen_row,
+                        (screen_row, true) => screen_row % 32,
+                        (_, false) => break,
+                    };
+                    let shifted = if self.quirks.wrap {
+                        sprite_row.rotate_left(x as u32)
+                    } else {
+                        sprite_row << x
+                    };
+
+                    if self.vram[screen_row] & shifted != 0 {
                         self.v[0xF] = 1;
                     }
+
+                    self.vram[screen_row] ^= shifted;
                 }
 
-                self.display.draw(&self.vram);
+                self.dirty = true;
             }
             0xE => {
                 match opcode & 0xFF {
@@ -430,7 +818,7 @@ where
                         console::debug(|| {
                             format!("{:x}: skip next op if key {} is pressed", opcode, x)
                         });
-                        if self.keyboard.is_key_pressed(Key::new(self.v[x])) {
+                        if self.is_key_pressed(Key::new(self.v[x])) {
                             self.pc += 2;
                         }
                     }
@@ -439,11 +827,11 @@ where
                         console::debug(|| {
                             format!("{:x}: skip next op if key {} is NOT pressed", opcode, x)
                         });
-                        if !self.keyboard.is_key_pressed(Key::new(self.v[x])) {
+                        if !self.is_key_pressed(Key::new(self.v[x])) {
                             self.pc += 2;
                         }
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
             0xF => {
@@ -463,7 +851,7 @@ where
                             )
                         });
 
-                        match self.keyboard.get_pressed_key() {
+                        match self.get_pressed_key() {
                             Some(key) => self.v[x] = key.value,
                             None => self.pc -= 2,
                         }
@@ -495,7 +883,7 @@ where
                         console::debug(|| {
                             format!("{:x}: set i to sprite value of {:x}", opcode, self.v[x],)
                         });
-                        self.i = SPRITE_POINTER + self.v[x] as usize * SPRITE_SIZE;
+                        self.i = self.font_base + self.v[x] as usize * SPRITE_SIZE;
                     }
                     0x33 => {
                         // BCD of v[x] in i, i+1, i+2
@@ -511,12 +899,12 @@ where
                         let val = self.v[x];
 
                         self.ram[self.i] = val / 100;
-                        self.ram[self.i + 1] = (val / 10) % 10;
-                        self.ram[self.i + 2] = val % 10;
+                        self.ram[(self.i + 1) & 0xFFF] = (val / 10) % 10;
+                        self.ram[(self.i + 2) & 0xFFF] = val % 10;
                     }
                     0x55 => {
                         // store v[0]..=v[x] in memory starting at i
-                        // sets i to i + n + 1
+                        // sets i to i + x + 1, or as set by the memory quirks
                         console::debug(|| {
                             format!("{}: store v[0] through v[{}] starting at i", opcode, x)
                         });
@@ -526,44 +914,85 @@ where
                         }
 
                         for n in 0..=x {
-                            self.ram[self.i] = self.v[n];
-                            self.i += 1;
+                            self.ram[(self.i + n) & 0xFFF] = self.v[n];
                         }
+                        self.increment_i(x);
                     }
                     0x65 => {
                         // read memory into v[0] through v[x] starting at i
-                        // sets i to i + n + 1
+                        // sets i to i + x + 1, or as set by the memory quirks
                         console::debug(|| {
                             format!("{}: read into v[0] through v[{}] starting at i", opcode, x)
                         });
 
                         for n in 0..=x {
-                            self.v[n] = self.ram[self.i];
-                            self.i += 1;
+                            self.v[n] = self.ram[(self.i + n) & 0xFFF];
                         }
+                        self.increment_i(x);
                     }
-                    _ => self.panic("bad opcode"),
+                    _ => self.bad_opcode(opcode),
                 }
             }
-            _ => self.panic("bad opcode"),
+            _ => self.bad_opcode(opcode),
         }
 
-        // update timers & cycle
-        if self.cycle & 0x7 == 0 {
-            // sound timers are reduced every 8 cycles, or approx. 60 Hz
-            self.dt = self.dt.saturating_sub(1);
-            self.st = self.st.saturating_sub(1);
+        // addresses wrap around at the end of memory, as on the VIP
+        self.pc &= 0xFFF;
+        self.i &= 0xFFF;
 
-            if self.st == 0 {
-                self.sound.stop();
+        // update timers & cycle
+        if self.vip_timing {
+            let skipped = self.pc == (start + 4) & 0xFFF;
+            let mut cost = vip_cycles(opcode, skipped);
+
+            // a slow instruction can run into the next frame
+            while cost >= self.budget {
+                cost -= self.budget;
+                self.end_frame();
             }
+            self.budget -= cost;
+        } else if self.cycle.is_multiple_of(self.cycles_per_frame) {
+            self.tick_timers();
         }
         self.cycle += 1;
     }
 
-    pub fn sleep(&self) {
-        let dur = time::Duration::from_millis(2);
-        thread::sleep(dur);
+    // once per frame, the screen is drawn along with the timers update
+    fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+
+        if self.st == 0 {
+            self.sound.stop();
+        }
+        self.present();
+    }
+
+    // draws the screen if it changed since it was last drawn
+    pub fn present(&mut self) {
+        if self.dirty {
+            self.display.draw(&self.vram);
+            self.dirty = false;
+        }
+    }
+
+    // with VIP timing, the display interrupt
+    fn end_frame(&mut self) {
+        self.tick_timers();
+        self.frame += 1;
+        self.budget = VIP_CYCLES_PER_FRAME;
+    }
+
+    fn increment_i(&mut self, x: usize) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+
+        self.i += if self.quirks.memory_increment_by_x {
+            x
+        } else {
+            x + 1
+        };
     }
 
     fn print_registers(&self) {
@@ -585,6 +1014,17 @@ where
         }
     }
 
+    fn bad_opcode(&self, opcode: u16) {
+        match opcode::platform(opcode) {
+            Some(platform) => self.panic(&format!(
+                "opcode {:04x} needs the {} platform, which is not supported",
+                opcode,
+                platform.id()
+            )),
+            None => self.panic("bad opcode"),
+        }
+    }
+
     fn panic(&self, msg: &str) {
         console::debug(|| format!("panic: {}", msg));
         self.print_registers();
@@ -592,3 +1032,115 @@ where
         panic!("{}", msg);
     }
 }
+
+// Approximate cost of an instruction in COSMAC VIP machine cycles, including
+// the interpreter's fetch and decode. Conditional skips take longer when they
+// skip, and DXYN is charged for drawing after its wait for the display.
+fn vip_cycles(opcode: u16, skipped: bool) -> usize {
+    let x = ((opcode >> 8) & 0xF) as usize;
+    let n = (opcode & 0xF) as usize;
+    let skip = if skipped { 2 } else { 0 };
+
+    match opcode >> 12 {
+        0x0 if opcode == 0x00E0 => 24,
+        0x0 | 0x1 | 0x2 | 0xB => 23,
+        0x3 | 0x4 => 12 + skip,
+        0x5 | 0x9 | 0xE => 16 + skip,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xC => 36,
+        0xD => 26 + 13 * n,
+        _ => match opcode & 0xFF {
+            0x1E => 19,
+            0x29 => 20,
+            0x33 => 204,
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 10,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::headless::HeadlessChip8;
+
+    fn run(rom: &[u8], steps: usize) -> HeadlessChip8 {
+        let mut c8 = HeadlessChip8::headless();
+        c8.load(rom);
+        for _ in 0..steps {
+            c8.step_instruction();
+        }
+        c8
+    }
+
+    // sound for 255 frames, then draw the font's 0 and loop
+    const BEEP: [u8; 12] = [
+        0x60, 0xFF, 0xF0, 0x18, 0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x0A,
+    ];
+
+    #[test]
+    fn snapshot_round_trips_through_bytes() {
+        let mut c8 = run(&BEEP, 0);
+        c8.step_frame();
+        let bytes = c8.snapshot().to_bytes();
+        assert_eq!(bytes.len(), Snapshot::SIZE);
+
+        let mut restored = HeadlessChip8::headless();
+        restored.restore(&Snapshot::from_bytes(&bytes).unwrap());
+        assert_eq!(restored.snapshot().to_bytes(), bytes);
+
+        c8.step_frame();
+        restored.step_frame();
+        assert_eq!(restored.snapshot().to_bytes(), c8.snapshot().to_bytes());
+        assert_eq!(restored.framebuffer()[0], 0b1111);
+    }
+
+    #[test]
+    fn snapshot_rejects_out_of_range_registers() {
+        let bytes = run(&BEEP, 0).snapshot().to_bytes();
+        assert!(Snapshot::from_bytes(&bytes[1..]).is_none());
+
+        // i, pc and sp follow cycle, frame and budget
+        for (offset, value) in [(24, 0x1000), (32, 0x1000), (40, 0x22)] {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 8].copy_from_slice(&(value as u64).to_le_bytes());
+            assert!(Snapshot::from_bytes(&bytes).is_none());
+        }
+    }
+
+    #[test]
+    fn memory_access_wraps_at_the_end_of_memory() {
+        // I = 0xFFF, draw 15 rows, v[0] = 0x12, store v[0] and v[1] at I
+        let c8 = run(&[0xAF, 0xFF, 0xD0, 0x0F, 0x60, 0x12, 0xF1, 0x55], 4);
+
+        assert_eq!(c8.ram()[0xFFF], 0x12);
+        assert_eq!(c8.i(), 0x001);
+    }
+
+    #[test]
+    fn pc_wraps_at_the_end_of_memory() {
+        let mut c8 = run(&[], 0);
+        c8.set_pc(0x1FFE);
+        assert_eq!(c8.pc(), 0xFFE);
+
+        c8.poke(0x1FFE, 0x60);
+        c8.poke(0xFFF, 0x34);
+        c8.step_instruction();
+
+        assert_eq!(c8.v()[0], 0x34);
+        assert_eq!(c8.pc(), 0x000);
+    }
+
+    #[test]
+    fn setters_mask_out_of_range_values() {
+        let mut c8 = run(&[], 0);
+        c8.set_i(0x1234);
+        c8.set_v(0x1F, 0x56);
+
+        assert_eq!(c8.i(), 0x234);
+        assert_eq!(c8.v()[0xF], 0x56);
+    }
+}
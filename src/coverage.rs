@@ -0,0 +1,144 @@
+// Memory coverage map, records how each byte of ram was used during a run:
+// fetched as an opcode, read as data by DXYN/FX65 or written by FX33/FX55.
+//
+// Exported either as JSON (when the file name ends in .json) or as an
+// annotated hex map with one marker per byte:
+//
+//   C  executed as code    R  read as data    W  written
+//   *  more than one of the above             .  never touched
+
+use crate::chip_8::{Chip8, DisplayInterface, KeyboardInterface, SoundInterface};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const EXECUTED: u8 = 0x1;
+const READ: u8 = 0x2;
+const WRITTEN: u8 = 0x4;
+
+const ROW_SIZE: usize = 0x10;
+
+pub struct Coverage {
+    flags: [u8; 0x1000],
+}
+
+#[derive(Serialize)]
+struct Range {
+    start: usize,
+    end: usize,
+    executed: bool,
+    read: bool,
+    written: bool,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { flags: [0; 0x1000] }
+    }
+
+    // call right before Chip8::execute
+    pub fn record<K, D, S>(&mut self, c8: &Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let pc = c8.pc();
+        let op = c8.peek_op();
+        let i = c8.i();
+        let x = ((op >> 8) & 0xF) as usize;
+
+        self.mark(pc, 2, EXECUTED);
+
+        match (op >> 12, op & 0xFF) {
+            (0xD, _) => self.mark(i, (op & 0xF) as usize, READ),
+            (0xF, 0x65) => self.mark(i, x + 1, READ),
+            (0xF, 0x33) => self.mark(i, 3, WRITTEN),
+            (0xF, 0x55) => self.mark(i, x + 1, WRITTEN),
+            _ => (),
+        }
+    }
+
+    fn mark(&mut self, addr: usize, len: usize, flag: u8) {
+        let end = (addr + len).min(self.flags.len());
+        for flags in self.flags[addr.min(end)..end].iter_mut() {
+            *flags |= flag;
+        }
+    }
+
+    fn marker(flags: u8) -> char {
+        match flags {
+            0 => '.',
+            EXECUTED => 'C',
+            READ => 'R',
+            WRITTEN => 'W',
+            _ => '*',
+        }
+    }
+
+    fn ranges(&self) -> Vec<Range> {
+        let mut ranges: Vec<Range> = Vec::new();
+
+        for (addr, &flags) in self.flags.iter().enumerate() {
+            let range = Range {
+                start: addr,
+                end: addr + 1,
+                executed: flags & EXECUTED != 0,
+                read: flags & READ != 0,
+                written: flags & WRITTEN != 0,
+            };
+
+            match ranges.last_mut() {
+                Some(last)
+                    if (last.executed, last.read, last.written)
+                        == (range.executed, range.read, range.written) =>
+                {
+                    last.end = range.end
+                }
+                _ => ranges.push(range),
+            }
+        }
+
+        ranges
+    }
+
+    fn hex_map(&self, ram: &[u8]) -> String {
+        let mut out = String::new();
+        let mut skipping = false;
+
+        for start in (0..ram.len()).step_by(ROW_SIZE) {
+            let row = &ram[start..start + ROW_SIZE];
+            let flags = &self.flags[start..start + ROW_SIZE];
+
+            // collapse rows that are empty and untouched, like hexdump does
+            if row.iter().all(|&byte| byte == 0) && flags.iter().all(|&f| f == 0) {
+                if !skipping {
+                    out.push_str("*\n");
+                    skipping = true;
+                }
+                continue;
+            }
+            skipping = false;
+
+            out.push_str(&format!("{:03x}:", start));
+            for byte in row {
+                out.push_str(&format!(" {:02x}", byte));
+            }
+            out.push_str("  ");
+            out.extend(flags.iter().map(|&f| Coverage::marker(f)));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    pub fn write(&self, path: &Path, ram: &[u8]) -> io::Result<()> {
+        if path.extension().is_some_and(|ext| ext == "json") {
+            fs::write(path, serde_json::to_string_pretty(&self.ranges())?)
+        } else {
+            let legend = "# C code, R data read, W written, * mixed, . untouched\n";
+            fs::write(path, String::from(legend) + &self.hex_map(ram))
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Running the machine so that a panic, from a bad opcode say, comes back as
+// an error instead of unwinding into the caller. Hosts embedding the
+// interpreter show the error their own way, so while catching, panics aren't
+// printed to stderr.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Wraps the current panic hook in one that stays quiet while catching. Done
+// once, on first use, or earlier by a frontend that installs its own hook on
+// top and puts this one back when it's done.
+pub fn install_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !catching() {
+                hook(info);
+            }
+        }));
+    });
+}
+
+// whether a panic now would be caught by catch
+pub fn catching() -> bool {
+    CATCHING.with(Cell::get)
+}
+
+// runs f, returning the panic message if it panics
+pub fn catch<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    install_hook();
+
+    let outer = CATCHING.with(|catching| catching.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(outer));
+
+    result.map_err(|payload| message(payload.as_ref()))
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("panic")
+    }
+}
@@ -0,0 +1,219 @@
+// ROM metadata, looked up by the SHA-1 hash of the ROM file.
+//
+// data/programs.json is bundled into the binary and uses the programs.json
+// format of the community CHIP-8 database, https://github.com/chip-8/chip-8-database
+// The bundled copy only has a few entries. --database points at a full copy
+// of that file, which is searched first.
+
+use crate::chip_8::Quirks;
+use crate::platform::Platform;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const PROGRAMS: &str = include_str!("../data/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    tickrate: Option<usize>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+pub struct Entry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub speed: Option<usize>,
+    // background and foreground as 0xRRGGBB
+    pub palette: Option<(u32, u32)>,
+    // game keys such as "up" or "a", and the Chip-8 key each is bound to
+    pub keys: Vec<(String, u8)>,
+}
+
+pub fn hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+}
+
+impl Database {
+    pub fn bundled() -> Self {
+        // the bundled file is checked when it is updated, a bad one is a build mistake
+        Database::parse(PROGRAMS).expect("bundled ROM database is invalid")
+    }
+
+    // a programs.json file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Database::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(data: &str) -> Result<Self, String> {
+        let programs = serde_json::from_str(data).map_err(|e| e.to_string())?;
+        Ok(Database { programs })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<Entry> {
+        let hash = hash(rom);
+        self.programs
+            .iter()
+            .find_map(|program| program.entry(&hash))
+    }
+}
+
+impl Program {
+    fn entry(&self, hash: &str) -> Option<Entry> {
+        let rom = self.roms.get(hash)?;
+
+        // the first platform this interpreter knows about
+        let platform = rom.platforms.iter().find_map(|id| id.parse().ok());
+        let quirks = platform.map(|platform: Platform| {
+            let mut quirks = platform.quirks();
+            if let Some(overrides) = rom.quirky_platforms.get(platform.id()) {
+                for (name, &value) in overrides {
+                    // quirks this interpreter doesn't implement are skipped
+                    let _ = quirks.set(name, value);
+                }
+            }
+            quirks
+        });
+
+        let palette = rom.colors.as_ref().and_then(|colors| {
+            match (colors.pixels.first(), colors.pixels.get(1)) {
+                (Some(bg), Some(fg)) => Some((parse_color(bg)?, parse_color(fg)?)),
+                _ => None,
+            }
+        });
+
+        let mut keys: Vec<(String, u8)> = rom
+            .keys
+            .iter()
+            .filter(|(_, &key)| key <= 0xF)
+            .map(|(name, &key)| (name.clone(), key))
+            .collect();
+        keys.sort();
+
+        Some(Entry {
+            title: self.title.clone(),
+            authors: self.authors.clone(),
+            platform,
+            quirks,
+            speed: rom.tickrate,
+            palette,
+            keys,
+        })
+    }
+}
+
+// "#RRGGBB" or "RRGGBB"
+pub fn parse_color(s: &str) -> Option<u32> {
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IBM_LOGO: &str = "\
+        00e0a22a600c6108d01f7009a239d01fa2487008d01f7004a257d01f7008a266d01f\
+        7008a275d01f1228ff00ff003c003c003c003c00ff00ffff00ff0038003f003f0038\
+        00ff00ff8000e000e00080008000e000e00080f800fc003e003f003b003900f800f8\
+        030007000f00bf00fb00f300e30043e000e0008000800080008000e000e0";
+
+    fn rom(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|n| u8::from_str_radix(&hex[n..n + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bundled_rom_resolves_to_its_platform_quirks() {
+        let rom = rom(IBM_LOGO);
+        assert_eq!(hash(&rom), "1ba58656810b67fd131eb9af3e3987863bf26c90");
+
+        let entry = Database::bundled().lookup(&rom).unwrap();
+        assert_eq!(entry.title, "IBM Logo");
+        assert_eq!(entry.platform, Some(Platform::OriginalChip8));
+        assert_eq!(entry.quirks, Some(Platform::OriginalChip8.quirks()));
+        assert!(entry.quirks.unwrap().logic);
+
+        assert!(Database::bundled().lookup(&rom[1..]).is_none());
+    }
+
+    #[test]
+    fn quirky_platforms_override_the_platform_quirks() {
+        let rom = [0x12, 0x00];
+        let database = format!(
+            r##"[{{
+                "title": "Loop",
+                "authors": ["Nobody"],
+                "roms": {{
+                    "{}": {{
+                        "platforms": ["chip8x", "superchip"],
+                        "quirkyPlatforms": {{"superchip": {{"shift": false, "vblank": true}}}},
+                        "tickrate": 30,
+                        "colors": {{"pixels": ["#000080", "ffff00"]}},
+                        "keys": {{"up": 5, "a": 16}}
+                    }}
+                }}
+            }}]"##,
+            hash(&rom)
+        );
+
+        let entry = Database::parse(&database).unwrap().lookup(&rom).unwrap();
+        assert_eq!(entry.authors, ["Nobody"]);
+        // chip8x isn't supported, so the first platform that is
+        assert_eq!(entry.platform, Some(Platform::Superchip));
+        assert_eq!(
+            entry.quirks,
+            Some(Quirks {
+                shift: false,
+                ..Platform::Superchip.quirks()
+            })
+        );
+        assert_eq!(entry.speed, Some(30));
+        assert_eq!(entry.palette, Some((0x000080, 0xFFFF00)));
+        assert_eq!(entry.keys, [(String::from("up"), 5)]);
+    }
+
+    #[test]
+    fn rejects_a_malformed_database() {
+        assert!(Database::parse("[]")
+            .unwrap()
+            .lookup(&[0x12, 0x00])
+            .is_none());
+        assert!(Database::parse("{").is_err());
+        assert!(Database::parse(r#"[{"roms": {}}]"#).is_err());
+    }
+}
@@ -0,0 +1,515 @@
+// Interactive debugger, driven from the terminal while the ROM runs.
+//
+// Before every instruction the registers are saved to the history, along
+// with the memory and screen rows the instruction can write. That keeps
+// recording cheap enough to leave on while the ROM runs, and stepping
+// backwards writes the old values back into the current state.
+
+use crate::cheats::{CheatSearch, Cheats, Filter};
+use crate::chip_8::{
+    Chip8, DisplayInterface, KeyboardInterface, Registers, Snapshot, SoundInterface,
+};
+use crate::opcode;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+
+// instructions kept for reverse stepping, the oldest are dropped first
+const HISTORY_LIMIT: usize = 100_000;
+
+// cheat search candidates printed by 'search list'
+const SEARCH_LIST_LIMIT: usize = 64;
+
+const HELP: &str = "\
+commands:
+  s, step [n]              execute n instructions (default 1)
+  b, back [n]              step back n instructions (default 1)
+  c, continue              run until a breakpoint or watchpoint is hit
+  rc, reverse-continue     run backwards to the previous breakpoint or watchpoint hit
+  break <addr>             set a breakpoint
+  delete <addr>            remove a breakpoint
+  watch <addr>             stop when the byte at addr changes
+  unwatch <addr>           remove a watchpoint
+  list                     list breakpoints, watchpoints and frozen memory
+  r, regs                  print registers
+  x, mem <addr> [len]      print memory
+  search                   start a cheat search from the current memory
+  search eq <n>            keep addresses now equal to n
+  search inc|dec|same      keep addresses that increased, decreased or didn't change
+  search list              list the remaining addresses
+  poke <addr> <n>          write n to memory
+  freeze <addr> <n>        hold memory at n, written every frame
+  unfreeze <addr>          release frozen memory
+  q, quit                  exit the interpreter
+an empty line repeats the last command, press F12 in the window to break";
+
+// what an instruction can change, with the values from before it ran
+struct Undo {
+    registers: Registers,
+    ram: Vec<(usize, u8)>,
+    vram: Vec<(usize, u64)>,
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, u8>,
+    history: VecDeque<Undo>,
+    remaining: Option<usize>,
+    last_command: String,
+    search: CheatSearch,
+}
+
+impl Debugger {
+    // starts paused before the first instruction
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            history: VecDeque::new(),
+            remaining: Some(0),
+            last_command: String::new(),
+            search: CheatSearch::new(),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.remaining = Some(0);
+    }
+
+    // call when the machine is reset or reloaded, which can't be undone
+    pub fn forget(&mut self) {
+        self.history.clear();
+    }
+
+    // Call right before Chip8::execute. Drops into the prompt if execution
+    // should stop here, then records the instruction. Returns false when the
+    // user asked to quit.
+    pub fn before<K, D, S>(&mut self, c8: &mut Chip8<K, D, S>, cheats: &mut Cheats) -> bool
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let mut stop = self.remaining == Some(0);
+
+        if self.breakpoints.contains(&c8.pc()) {
+            println!("breakpoint at {:#05x}", c8.pc());
+            stop = true;
+        }
+        if self.check_watchpoints(c8) {
+            stop = true;
+        }
+
+        if stop {
+            // show the screen as it is now, not as of the last frame
+            c8.present();
+            if !self.prompt(c8, cheats) {
+                return false;
+            }
+        }
+
+        if let Some(n) = self.remaining.as_mut() {
+            *n = n.saturating_sub(1);
+        }
+        self.record(c8);
+
+        true
+    }
+
+    fn record<K, D, S>(&mut self, c8: &Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let registers = c8.registers();
+        let op = c8.peek_op();
+        let x = ((op >> 8) & 0xF) as usize;
+        let y = ((op >> 4) & 0xF) as usize;
+
+        // memory the instruction writes: the stack for a call, from i for
+        // FX33 and FX55
+        let ram: Vec<usize> = match (op >> 12, op & 0xFF) {
+            (0x2, _) => (registers.sp..registers.sp + 2).collect(),
+            (0xF, 0x33) => (0..3).map(|n| (registers.i + n) & 0xFFF).collect(),
+            (0xF, 0x55) => (0..=x).map(|n| (registers.i + n) & 0xFFF).collect(),
+            _ => Vec::new(),
+        };
+        // screen rows it draws on, wrapping or not
+        let vram: Vec<usize> = match op >> 12 {
+            0x0 if op == 0x00E0 => (0..0x20).collect(),
+            0xD => (0..(op & 0xF) as usize)
+                .map(|row| (registers.v[y] as usize + row) % 0x20)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let undo = Undo {
+            registers,
+            ram: ram.into_iter().map(|addr| (addr, c8.ram()[addr])).collect(),
+            vram: vram
+                .into_iter()
+                .map(|row| (row, c8.framebuffer()[row]))
+                .collect(),
+        };
+
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(undo);
+    }
+
+    // reports watched bytes that changed since they were last seen
+    fn check_watchpoints<K, D, S>(&mut self, c8: &Chip8<K, D, S>) -> bool
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let mut hit = false;
+
+        for (&addr, last) in self.watchpoints.iter_mut() {
+            let value = c8.ram()[addr];
+            if value != *last {
+                println!("watchpoint {:#05x}: {:#04x} -> {:#04x}", addr, *last, value);
+                *last = value;
+                hit = true;
+            }
+        }
+
+        hit
+    }
+
+    fn refresh_watchpoints<K, D, S>(&mut self, c8: &Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        for (&addr, last) in self.watchpoints.iter_mut() {
+            *last = c8.ram()[addr];
+        }
+    }
+
+    // undoes the last instruction in `snapshot`, returns it or None if the
+    // history is empty
+    fn step_back(&mut self, snapshot: &mut Snapshot) -> Option<Undo> {
+        let undo = self.history.pop_back()?;
+        let registers = undo.registers;
+
+        snapshot.cycle = registers.cycle;
+        snapshot.frame = registers.frame;
+        snapshot.budget = registers.budget;
+        snapshot.v = registers.v;
+        snapshot.i = registers.i;
+        snapshot.dt = registers.dt;
+        snapshot.st = registers.st;
+        snapshot.pc = registers.pc;
+        snapshot.sp = registers.sp;
+        for &(addr, byte) in &undo.ram {
+            snapshot.ram[addr] = byte;
+        }
+        for &(row, bits) in &undo.vram {
+            snapshot.vram[row] = bits;
+        }
+
+        Some(undo)
+    }
+
+    fn back<K, D, S>(&mut self, c8: &mut Chip8<K, D, S>, n: usize)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let mut snapshot = c8.snapshot();
+
+        for _ in 0..n {
+            if self.step_back(&mut snapshot).is_none() {
+                println!("no more history");
+                break;
+            }
+        }
+
+        c8.restore(&snapshot);
+        self.refresh_watchpoints(c8);
+    }
+
+    fn reverse_continue<K, D, S>(&mut self, c8: &mut Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let mut snapshot = c8.snapshot();
+
+        loop {
+            // the bytes it could write, compared with what they are now
+            let written = self.history.back().map(|undo| {
+                undo.ram
+                    .iter()
+                    .find(|&&(addr, byte)| {
+                        self.watchpoints.contains_key(&addr) && snapshot.ram[addr] != byte
+                    })
+                    .map(|&(addr, _)| addr)
+            });
+
+            if self.step_back(&mut snapshot).is_none() {
+                println!("no more history");
+                break;
+            }
+
+            if let Some(Some(addr)) = written {
+                println!("watchpoint {:#05x} written by the next instruction", addr);
+                break;
+            }
+            if self.breakpoints.contains(&snapshot.pc) {
+                println!("breakpoint at {:#05x}", snapshot.pc);
+                break;
+            }
+        }
+
+        c8.restore(&snapshot);
+        self.refresh_watchpoints(c8);
+    }
+
+    // returns false when the user asked to quit
+    fn prompt<K, D, S>(&mut self, c8: &mut Chip8<K, D, S>, cheats: &mut Cheats) -> bool
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        loop {
+            println!("{}", Debugger::location(c8));
+            print!("(chip-8) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                // stdin closed
+                return false;
+            }
+
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+
+            let count = match args.first().map(|arg| arg.parse::<usize>()) {
+                None => Ok(1),
+                Some(Ok(n)) if n > 0 => Ok(n),
+                Some(_) => Err(()),
+            };
+            let addr = args.first().map(|arg| parse_addr(arg));
+            let value = args.get(1).map(|arg| parse_value(arg));
+
+            match (command, count, addr) {
+                ("s" | "step", Ok(n), _) => {
+                    self.remaining = Some(n);
+                    return true;
+                }
+                ("b" | "back", Ok(n), _) => self.back(c8, n),
+                ("c" | "continue", _, _) => {
+                    self.remaining = None;
+                    return true;
+                }
+                ("rc" | "reverse-continue", _, _) => self.reverse_continue(c8),
+                ("break", _, Some(Some(addr))) => {
+                    self.breakpoints.insert(addr);
+                }
+                ("delete", _, Some(Some(addr))) => {
+                    self.breakpoints.remove(&addr);
+                }
+                ("watch", _, Some(Some(addr))) => {
+                    self.watchpoints.insert(addr, c8.ram()[addr]);
+                }
+                ("unwatch", _, Some(Some(addr))) => {
+                    self.watchpoints.remove(&addr);
+                }
+                ("list", _, _) => {
+                    for addr in &self.breakpoints {
+                        println!("breakpoint {:#05x}", addr);
+                    }
+                    for addr in self.watchpoints.keys() {
+                        println!("watchpoint {:#05x}", addr);
+                    }
+                    for (addr, value) in cheats.frozen() {
+                        println!("frozen {:#05x} = {}", addr, value);
+                    }
+                }
+                ("search", _, _) => self.search(c8, &args),
+                ("poke", _, Some(Some(addr))) => match value {
+                    Some(Some(value)) => c8.poke(addr, value),
+                    _ => println!("expected a value from 0 to 255"),
+                },
+                ("freeze", _, Some(Some(addr))) => match value {
+                    Some(Some(value)) => {
+                        cheats.freeze(addr, value);
+                        c8.poke(addr, value);
+                    }
+                    _ => println!("expected a value from 0 to 255"),
+                },
+                ("unfreeze", _, Some(Some(addr))) => cheats.unfreeze(addr),
+                ("r" | "regs", _, _) => Debugger::print_registers(c8),
+                ("x" | "mem", _, Some(Some(addr))) => {
+                    let len = args.get(1).and_then(|len| len.parse().ok()).unwrap_or(16);
+                    Debugger::print_memory(c8, addr, len);
+                }
+                ("q" | "quit", _, _) => return false,
+                ("h" | "help", _, _) => println!("{}", HELP),
+                _ => println!("bad command, try 'help'"),
+            }
+        }
+    }
+
+    fn search<K, D, S>(&mut self, c8: &Chip8<K, D, S>, args: &[&str])
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let filter = match args {
+            [] => {
+                self.search.start(c8.ram());
+                println!("{} candidates", self.search.candidates().len());
+                return;
+            }
+            ["list"] => {
+                for &addr in self.search.candidates().iter().take(SEARCH_LIST_LIMIT) {
+                    println!("{:#05x}: {}", addr, c8.ram()[addr]);
+                }
+                if self.search.candidates().len() > SEARCH_LIST_LIMIT {
+                    println!(
+                        "... {} more",
+                        self.search.candidates().len() - SEARCH_LIST_LIMIT
+                    );
+                }
+                return;
+            }
+            ["eq", n] => match parse_value(n) {
+                Some(n) => Filter::Equal(n),
+                None => {
+                    println!("expected a value from 0 to 255");
+                    return;
+                }
+            },
+            ["inc"] => Filter::Increased,
+            ["dec"] => Filter::Decreased,
+            ["same"] => Filter::Unchanged,
+            _ => {
+                println!("bad search, try 'help'");
+                return;
+            }
+        };
+
+        match self.search.filter(c8.ram(), filter) {
+            Some(n) => println!("{} candidates", n),
+            None => println!("no search started, use 'search' first"),
+        }
+    }
+
+    fn location<K, D, S>(c8: &Chip8<K, D, S>) -> String
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let op = c8.peek_op();
+        format!(
+            "{:#05x}: {:04x} {}",
+            c8.pc(),
+            op,
+            opcode::pattern(op).unwrap_or("(bad opcode)")
+        )
+    }
+
+    fn print_registers<K, D, S>(c8: &Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let snapshot = c8.snapshot();
+        for (n, v) in snapshot.v.iter().enumerate() {
+            print!(
+                "v[{:x}]: {:#04x}{}",
+                n,
+                v,
+                if n % 4 == 3 { "\n" } else { "  " }
+            );
+        }
+        println!(
+            "i: {:#05x}  dt: {:#04x}  st: {:#04x}  pc: {:#05x}  sp: {:#04x}  cycle: {}",
+            snapshot.i, snapshot.dt, snapshot.st, snapshot.pc, snapshot.sp, snapshot.cycle
+        );
+    }
+
+    fn print_memory<K, D, S>(c8: &Chip8<K, D, S>, addr: usize, len: usize)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let end = (addr + len).min(c8.ram().len());
+        for start in (addr..end).step_by(16) {
+            print!("{:#05x}:", start);
+            for byte in &c8.ram()[start..(start + 16).min(end)] {
+                print!(" {:02x}", byte);
+            }
+            println!();
+        }
+    }
+}
+
+// values are decimal, or hex with a 0x prefix
+fn parse_value(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// addresses are hex, with or without a 0x prefix
+fn parse_addr(s: &str) -> Option<usize> {
+    usize::from_str_radix(s.trim_start_matches("0x"), 16)
+        .ok()
+        .filter(|&addr| addr < 0x1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessChip8;
+
+    #[test]
+    fn stepping_back_undoes_every_write() {
+        // v[0..2] stored at 0x300, BCD at 0x302, a call, a sprite, a clear
+        let rom = [
+            0x60, 0x12, 0x61, 0x34, 0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x33, 0x22, 0x0E, 0x00, 0x00,
+            0x60, 0x3E, 0x61, 0x1E, 0xF0, 0x29, 0xD0, 0x15, 0x00, 0xE0, 0x12, 0x0E,
+        ];
+        let mut c8 = HeadlessChip8::headless();
+        c8.load(&rom);
+        let start = c8.snapshot().to_bytes();
+
+        let mut debugger = Debugger::new();
+        let mut states = Vec::new();
+        for _ in 0..12 {
+            states.push(c8.snapshot().to_bytes());
+            debugger.record(&c8);
+            c8.execute();
+        }
+        assert_eq!(c8.ram()[0x300..0x305], [0x12, 0x34, 0x00, 0x05, 0x02]);
+
+        for state in states.iter().rev() {
+            debugger.back(&mut c8, 1);
+            assert_eq!(&c8.snapshot().to_bytes(), state);
+        }
+        assert_eq!(c8.snapshot().to_bytes(), start);
+    }
+}
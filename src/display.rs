@@ -1,53 +1,115 @@
+// The screen is written into a streaming texture the size of the Chip-8
+// display once per frame, and SDL scales it up to the window.
+
 use crate::chip_8::{BitMap, DisplayInterface};
-use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window, Sdl};
+use crate::frontend::StatusDisplay;
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    render::{Canvas, Texture},
+    video::Window,
+    Sdl,
+};
+
+pub const WIDTH: u32 = 64;
+pub const HEIGHT: u32 = 32;
 
 pub const SCALE_FACTOR: u32 = 20;
-pub const WINDOW_WIDTH: u32 = 64 * SCALE_FACTOR;
-pub const WINDOW_HEIGHT: u32 = 32 * SCALE_FACTOR;
+pub const WINDOW_WIDTH: u32 = WIDTH * SCALE_FACTOR;
+pub const WINDOW_HEIGHT: u32 = HEIGHT * SCALE_FACTOR;
+
+const TITLE: &str = "Chip-8";
+
+// bytes per pixel of the texture
+const PIXEL_SIZE: usize = 3;
 
 pub struct Display {
+    // with unsafe_textures dropping a texture doesn't free it, SDL frees it
+    // along with the canvas's renderer
+    texture: Texture,
     canvas: Canvas<Window>,
+    background: Color,
+    foreground: Color,
 }
 
 impl Display {
-    pub fn new(sdl_context: &Sdl) -> Self {
+    // uses the GPU when there is one, otherwise SDL's software renderer, or
+    // always the software renderer with `software`
+    pub fn new(sdl_context: &Sdl, software: bool) -> Self {
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window = video_subsystem
-            .window("Chip-8", WINDOW_WIDTH, WINDOW_HEIGHT)
-            .position_centered()
-            .build()
+        let window = || {
+            video_subsystem
+                .window(TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)
+                .position_centered()
+                .build()
+                .unwrap()
+        };
+
+        let accelerated = if software {
+            None
+        } else {
+            // no vsync, FrameLimiter paces frames, and waiting for the
+            // display would cap fast-forward and slow down 50 Hz displays
+            window().into_canvas().accelerated().build().ok()
+        };
+        let canvas =
+            accelerated.unwrap_or_else(|| window().into_canvas().software().build().unwrap());
+
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, WIDTH, HEIGHT)
             .unwrap();
 
-        let canvas = window.into_canvas().present_vsync().build().unwrap();
+        Display {
+            texture,
+            canvas,
+            background: Color::RGB(0, 0, 0),
+            foreground: Color::RGB(255, 255, 255),
+        }
+    }
 
-        Display { canvas }
+    // colors as 0xRRGGBB
+    pub fn set_palette(&mut self, background: u32, foreground: u32) {
+        let rgb = |c: u32| Color::RGB((c >> 16) as u8, (c >> 8) as u8, c as u8);
+        self.background = rgb(background);
+        self.foreground = rgb(foreground);
+    }
+}
+
+impl StatusDisplay for Display {
+    // shows a status after the name in the window title, or just the name
+    fn set_status(&mut self, status: &str) {
+        let title = if status.is_empty() {
+            String::from(TITLE)
+        } else {
+            format!("{} - {}", TITLE, status)
+        };
+        self.canvas.window_mut().set_title(&title).unwrap();
     }
 }
 
 impl DisplayInterface for Display {
     fn draw(&mut self, bitmap: &BitMap) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-        self.canvas.clear();
-
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-
-        for row in 0..32 {
-            let data = bitmap[row as usize];
-            for col in 0..64 {
-                if data & (1 << col) != 0 {
-                    let r = Rect::new(
-                        col * SCALE_FACTOR as i32,
-                        row * SCALE_FACTOR as i32,
-                        SCALE_FACTOR,
-                        SCALE_FACTOR,
-                    );
-                    self.canvas.draw_rect(r).unwrap();
-                    self.canvas.fill_rect(r).unwrap();
+        let (background, foreground) = (self.background, self.foreground);
+
+        self.texture
+            .with_lock(None, |pixels, pitch| {
+                for (y, &row) in bitmap.iter().enumerate() {
+                    for x in 0..WIDTH as usize {
+                        let color = if row & (1 << x) != 0 {
+                            foreground
+                        } else {
+                            background
+                        };
+
+                        let at = y * pitch + x * PIXEL_SIZE;
+                        pixels[at..at + PIXEL_SIZE].copy_from_slice(&[color.r, color.g, color.b]);
+                    }
                 }
-            }
-        }
+            })
+            .unwrap();
 
+        self.canvas.copy(&self.texture, None, None).unwrap();
         self.canvas.present();
     }
 }
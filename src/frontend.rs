@@ -0,0 +1,207 @@
+// Where the interpreter runs: in a window, in the terminal, or with no
+// output at all. A frontend makes the machine's keyboard, display and sound,
+// reports input and paces frames, so the main loop is written once for all
+// of them.
+
+use crate::chip_8::{Chip8, DisplayInterface, KeyboardInterface, SoundInterface};
+use crate::display::Display;
+use crate::headless::{NullDisplay, NullKeyboard, NullSound};
+use crate::keyboard::Keyboard;
+use crate::pacing::FrameLimiter;
+use crate::sound::Sound;
+use sdl2::{event::Event, keyboard::Scancode, EventPump};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Kind {
+    #[default]
+    Sdl,
+    Terminal,
+    Headless,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::Sdl, Kind::Terminal, Kind::Headless];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Kind::Sdl => "sdl",
+            Kind::Terminal => "terminal",
+            Kind::Headless => "headless",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Kind::ALL
+            .into_iter()
+            .find(|kind| kind.id() == id)
+            .ok_or_else(|| {
+                let ids: Vec<&str> = Kind::ALL.iter().map(|k| k.id()).collect();
+                format!(
+                    "unknown frontend '{}', expected one of {}",
+                    id,
+                    ids.join(", ")
+                )
+            })
+    }
+}
+
+// keys use SDL's scancodes whichever frontend reads them, so the keypad and
+// hotkeys are bound the same way everywhere
+pub enum Input {
+    Quit,
+    KeyDown(Scancode),
+    KeyUp(Scancode),
+}
+
+// device settings, each frontend uses the ones that apply to it
+pub struct Options {
+    // game keys, see Keyboard::set_game_keys
+    pub keys: Vec<(String, u8)>,
+    pub palette: Option<(u32, u32)>,
+    pub software_render: bool,
+    pub visual_bell: bool,
+}
+
+// a display with room for a line of status, e.g. the window title
+pub trait StatusDisplay: DisplayInterface {
+    fn set_status(&mut self, status: &str);
+}
+
+pub type Machine<F> =
+    Chip8<<F as Frontend>::Keyboard, <F as Frontend>::Display, <F as Frontend>::Sound>;
+
+pub trait Frontend: Sized {
+    type Keyboard: KeyboardInterface;
+    type Display: StatusDisplay;
+    type Sound: SoundInterface;
+
+    // the frontend and a machine using its devices
+    fn new(options: &Options) -> Result<(Self, Machine<Self>), String>;
+
+    // input since the last call, without waiting
+    fn poll(&mut self) -> Result<Vec<Input>, String>;
+
+    fn limiter(&mut self) -> &mut FrameLimiter;
+
+    // silences the beep, without changing when it starts and stops
+    fn set_muted(&mut self, _muted: bool) {}
+
+    fn print(&mut self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn print_error(&mut self, line: &str) {
+        eprintln!("error: {}", line);
+    }
+}
+
+// a window, with sound
+pub struct SdlFrontend {
+    // shared with the keyboard, which reads the keys held down from it
+    event_pump: Rc<RefCell<EventPump>>,
+    muted: Arc<AtomicBool>,
+    limiter: FrameLimiter,
+}
+
+impl Frontend for SdlFrontend {
+    type Keyboard = Keyboard<Rc<RefCell<EventPump>>>;
+    type Display = Display;
+    type Sound = Sound;
+
+    fn new(options: &Options) -> Result<(Self, Machine<Self>), String> {
+        let sdl_context = sdl2::init()?;
+
+        let event_pump = Rc::new(RefCell::new(sdl_context.event_pump()?));
+
+        let mut keyboard = Keyboard::new(Rc::clone(&event_pump));
+        for name in keyboard.set_game_keys(&options.keys) {
+            eprintln!("warning: unknown game key '{}'", name);
+        }
+
+        let mut display = Display::new(&sdl_context, options.software_render);
+        if let Some((background, foreground)) = options.palette {
+            display.set_palette(background, foreground);
+        }
+
+        let sound = Sound::new(&sdl_context);
+
+        let frontend = SdlFrontend {
+            event_pump,
+            muted: sound.mute_switch(),
+            limiter: FrameLimiter::new(),
+        };
+
+        Ok((frontend, Chip8::new(keyboard, display, sound)))
+    }
+
+    fn poll(&mut self) -> Result<Vec<Input>, String> {
+        let mut inputs = Vec::new();
+
+        for event in self.event_pump.borrow_mut().poll_iter() {
+            match event {
+                Event::Quit { .. } => inputs.push(Input::Quit),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => inputs.push(Input::KeyDown(scancode)),
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => inputs.push(Input::KeyUp(scancode)),
+                _ => (),
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    fn limiter(&mut self) -> &mut FrameLimiter {
+        &mut self.limiter
+    }
+
+    fn set_muted(&mut self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+}
+
+// runs as fast as possible with no input and nothing to show, for tracing,
+// profiling and coverage without a window
+pub struct HeadlessFrontend {
+    limiter: FrameLimiter,
+}
+
+impl StatusDisplay for NullDisplay {
+    fn set_status(&mut self, _status: &str) {}
+}
+
+impl Frontend for HeadlessFrontend {
+    type Keyboard = NullKeyboard;
+    type Display = NullDisplay;
+    type Sound = NullSound;
+
+    fn new(_options: &Options) -> Result<(Self, Machine<Self>), String> {
+        let frontend = HeadlessFrontend {
+            limiter: FrameLimiter::unlimited(),
+        };
+
+        Ok((frontend, Chip8::headless()))
+    }
+
+    fn poll(&mut self) -> Result<Vec<Input>, String> {
+        Ok(Vec::new())
+    }
+
+    fn limiter(&mut self) -> &mut FrameLimiter {
+        &mut self.limiter
+    }
+}
@@ -0,0 +1,39 @@
+// Devices that do nothing, for running the interpreter without a window.
+
+use crate::chip_8::{BitMap, Chip8, DisplayInterface, Key, KeyboardInterface, SoundInterface};
+
+pub type HeadlessChip8 = Chip8<NullKeyboard, NullDisplay, NullSound>;
+
+impl HeadlessChip8 {
+    // a machine driven entirely through the host API, see Chip8::step_frame
+    pub fn headless() -> Self {
+        Chip8::new(NullKeyboard, NullDisplay, NullSound)
+    }
+}
+
+// no key is ever pressed
+pub struct NullKeyboard;
+
+impl KeyboardInterface for NullKeyboard {
+    fn is_key_pressed(&self, _key: Key) -> bool {
+        false
+    }
+
+    fn get_pressed_key(&self) -> Option<Key> {
+        None
+    }
+}
+
+pub struct NullDisplay;
+
+impl DisplayInterface for NullDisplay {
+    fn draw(&mut self, _bitmap: &BitMap) {}
+}
+
+pub struct NullSound;
+
+impl SoundInterface for NullSound {
+    fn start(&self) {}
+
+    fn stop(&self) {}
+}
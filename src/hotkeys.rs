@@ -0,0 +1,169 @@
+// Interpreter controls bound to keys outside the Chip-8 keypad.
+
+use crate::keyboard;
+use sdl2::keyboard::Scancode;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Pause,
+    Advance,
+    Reset,
+    HardReset,
+    FastForward,
+    SlowMotion,
+    Cheats,
+    Debugger,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Pause,
+        Action::Advance,
+        Action::Reset,
+        Action::HardReset,
+        Action::FastForward,
+        Action::SlowMotion,
+        Action::Cheats,
+        Action::Debugger,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Pause => "pause",
+            Action::Advance => "advance",
+            Action::Reset => "reset",
+            Action::HardReset => "hard-reset",
+            Action::FastForward => "fast-forward",
+            Action::SlowMotion => "slow-motion",
+            Action::Cheats => "cheats",
+            Action::Debugger => "debugger",
+        }
+    }
+
+    fn default_key(&self) -> Scancode {
+        match self {
+            Action::Pause => Scancode::F5,
+            Action::Advance => Scancode::F6,
+            Action::Reset => Scancode::F7,
+            Action::HardReset => Scancode::F8,
+            Action::FastForward => Scancode::Tab,
+            Action::SlowMotion => Scancode::F10,
+            Action::Cheats => Scancode::F11,
+            Action::Debugger => Scancode::F12,
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Action::ALL.iter().map(|a| a.name()).collect();
+                format!(
+                    "unknown action '{}', expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+pub struct Hotkeys {
+    bindings: Vec<(Action, Scancode)>,
+}
+
+impl Hotkeys {
+    // The default bindings, with `overrides` of (action, SDL key name) on top.
+    // Each key can only do one thing, so a key bound twice, on the keypad or
+    // Escape is refused.
+    pub fn new(overrides: &[(Action, String)]) -> Result<Self, String> {
+        let mut bindings: Vec<(Action, Scancode)> = Action::ALL
+            .iter()
+            .map(|&action| (action, action.default_key()))
+            .collect();
+
+        for (action, name) in overrides {
+            let scancode = Scancode::from_name(name)
+                .ok_or_else(|| format!("unknown key '{}' for {}", name, action.name()))?;
+            for binding in bindings.iter_mut().filter(|(a, _)| a == action) {
+                binding.1 = scancode;
+            }
+        }
+
+        for (n, &(action, scancode)) in bindings.iter().enumerate() {
+            if let Some(&(other, _)) = bindings[..n].iter().find(|&&(_, key)| key == scancode) {
+                return Err(format!(
+                    "key '{}' is bound to both {} and {}",
+                    scancode.name(),
+                    other.name(),
+                    action.name()
+                ));
+            }
+            if keyboard::scancode_to_key(scancode).is_some() {
+                return Err(format!(
+                    "key '{}' for {} is on the Chip-8 keypad",
+                    scancode.name(),
+                    action.name()
+                ));
+            }
+            if scancode == Scancode::Escape {
+                return Err(format!("key 'Escape' for {} already quits", action.name()));
+            }
+        }
+
+        Ok(Hotkeys { bindings })
+    }
+
+    pub fn action(&self, scancode: Scancode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|&&(_, key)| key == scancode)
+            .map(|&(action, _)| action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(overrides: &[(Action, &str)]) -> Result<Hotkeys, String> {
+        let overrides: Vec<(Action, String)> = overrides
+            .iter()
+            .map(|&(action, name)| (action, name.to_string()))
+            .collect();
+        Hotkeys::new(&overrides)
+    }
+
+    #[test]
+    fn overrides_replace_the_default_key() {
+        let hotkeys = bind(&[(Action::Pause, "P")]).unwrap();
+        assert_eq!(hotkeys.action(Scancode::P), Some(Action::Pause));
+        assert_eq!(hotkeys.action(Scancode::F5), None);
+
+        // swapping two keys
+        let hotkeys = bind(&[(Action::Pause, "F6"), (Action::Advance, "F5")]).unwrap();
+        assert_eq!(hotkeys.action(Scancode::F6), Some(Action::Pause));
+    }
+
+    #[test]
+    fn rejects_conflicting_keys() {
+        assert_eq!(
+            bind(&[(Action::Pause, "F6")]).err().unwrap(),
+            "key 'F6' is bound to both pause and advance"
+        );
+        assert_eq!(
+            bind(&[(Action::Reset, "Q")]).err().unwrap(),
+            "key 'Q' for reset is on the Chip-8 keypad"
+        );
+        assert_eq!(
+            bind(&[(Action::Cheats, "Escape")]).err().unwrap(),
+            "key 'Escape' for cheats already quits"
+        );
+        assert!(bind(&[(Action::Cheats, "NoSuchKey")]).is_err());
+    }
+}
@@ -2,13 +2,62 @@ use crate::chip_8::{Key, KeyboardInterface};
 use sdl2::{keyboard::Scancode, EventPump};
 use std::{cell::RefCell, rc::Rc};
 
-pub struct Keyboard {
-    event_pump: Rc<RefCell<EventPump>>,
+// where the keys held down come from, SDL or the terminal
+pub trait PressedKeys {
+    fn is_pressed(&self, scancode: Scancode) -> bool;
+
+    fn pressed(&self) -> Vec<Scancode>;
 }
 
-impl Keyboard {
-    pub fn new(event_pump: Rc<RefCell<EventPump>>) -> Self {
-        Keyboard { event_pump }
+impl PressedKeys for Rc<RefCell<EventPump>> {
+    fn is_pressed(&self, scancode: Scancode) -> bool {
+        self.borrow().keyboard_state().is_scancode_pressed(scancode)
+    }
+
+    fn pressed(&self) -> Vec<Scancode> {
+        self.borrow().keyboard_state().pressed_scancodes().collect()
+    }
+}
+
+pub struct Keyboard<P: PressedKeys> {
+    keys: P,
+    game_keys: Vec<(Scancode, u8)>,
+}
+
+impl<P: PressedKeys> Keyboard<P> {
+    pub fn new(keys: P) -> Self {
+        Keyboard {
+            keys,
+            game_keys: Vec::new(),
+        }
+    }
+
+    // Binds game keys to Chip-8 keys on top of the keypad layout, e.g.
+    // ("up", 0x5) lets the up arrow press key 5. Returns the unknown names.
+    pub fn set_game_keys(&mut self, keys: &[(String, u8)]) -> Vec<String> {
+        let mut unknown = Vec::new();
+
+        self.game_keys.clear();
+        for (name, key) in keys {
+            match Self::game_key_to_scancode(name) {
+                Some(scancode) => self.game_keys.push((scancode, *key)),
+                None => unknown.push(name.clone()),
+            }
+        }
+
+        unknown
+    }
+
+    fn game_key_to_scancode(name: &str) -> Option<Scancode> {
+        match name {
+            "up" => Some(Scancode::Up),
+            "down" => Some(Scancode::Down),
+            "left" => Some(Scancode::Left),
+            "right" => Some(Scancode::Right),
+            "a" => Some(Scancode::Space),
+            "b" => Some(Scancode::LShift),
+            _ => None,
+        }
     }
 
     fn key_to_scancode(key: Key) -> Scancode {
@@ -32,46 +81,51 @@ impl Keyboard {
             _ => panic!(), // would have panicked in Key::new()
         }
     }
+}
 
-    fn scancode_to_key(scancode: Scancode) -> Option<Key> {
-        match scancode {
-            Scancode::Num1 => Some(Key::new(0x1)),
-            Scancode::Num2 => Some(Key::new(0x2)),
-            Scancode::Num3 => Some(Key::new(0x3)),
-            Scancode::Num4 => Some(Key::new(0xC)),
-            Scancode::Q => Some(Key::new(0x4)),
-            Scancode::W => Some(Key::new(0x5)),
-            Scancode::E => Some(Key::new(0x6)),
-            Scancode::R => Some(Key::new(0xD)),
-            Scancode::A => Some(Key::new(0x7)),
-            Scancode::S => Some(Key::new(0x8)),
-            Scancode::D => Some(Key::new(0x9)),
-            Scancode::F => Some(Key::new(0xE)),
-            Scancode::Z => Some(Key::new(0xA)),
-            Scancode::X => Some(Key::new(0x0)),
-            Scancode::C => Some(Key::new(0xB)),
-            Scancode::V => Some(Key::new(0xF)),
-            _ => None,
-        }
+// the Chip-8 key a key of the keypad layout stands for
+pub fn scancode_to_key(scancode: Scancode) -> Option<Key> {
+    match scancode {
+        Scancode::Num1 => Some(Key::new(0x1)),
+        Scancode::Num2 => Some(Key::new(0x2)),
+        Scancode::Num3 => Some(Key::new(0x3)),
+        Scancode::Num4 => Some(Key::new(0xC)),
+        Scancode::Q => Some(Key::new(0x4)),
+        Scancode::W => Some(Key::new(0x5)),
+        Scancode::E => Some(Key::new(0x6)),
+        Scancode::R => Some(Key::new(0xD)),
+        Scancode::A => Some(Key::new(0x7)),
+        Scancode::S => Some(Key::new(0x8)),
+        Scancode::D => Some(Key::new(0x9)),
+        Scancode::F => Some(Key::new(0xE)),
+        Scancode::Z => Some(Key::new(0xA)),
+        Scancode::X => Some(Key::new(0x0)),
+        Scancode::C => Some(Key::new(0xB)),
+        Scancode::V => Some(Key::new(0xF)),
+        _ => None,
     }
 }
 
-impl KeyboardInterface for Keyboard {
+impl<P: PressedKeys> KeyboardInterface for Keyboard<P> {
     fn is_key_pressed(&self, key: Key) -> bool {
-        let event_pump = self.event_pump.borrow();
-        let kbd_state = event_pump.keyboard_state();
-        let scancode = Keyboard::key_to_scancode(key);
-        kbd_state.is_scancode_pressed(scancode)
+        let value = *key.value();
+        let scancode = Self::key_to_scancode(key);
+
+        self.keys.is_pressed(scancode)
+            || self
+                .game_keys
+                .iter()
+                .any(|&(scancode, key)| key == value && self.keys.is_pressed(scancode))
     }
 
     fn get_pressed_key(&self) -> Option<Key> {
-        let event_pump = self.event_pump.borrow();
-        let kbd_state = event_pump.keyboard_state();
-
-        for scancode in kbd_state.pressed_scancodes() {
-            if let Some(key) = Keyboard::scancode_to_key(scancode) {
+        for scancode in self.keys.pressed() {
+            if let Some(key) = scancode_to_key(scancode) {
                 return Some(key);
             }
+            if let Some(&(_, key)) = self.game_keys.iter().find(|&&(s, _)| s == scancode) {
+                return Some(Key::new(key));
+            }
         }
 
         None
@@ -0,0 +1,12 @@
+// The interpreter core, for hosts embedding it. The SDL frontend and the
+// tools around it live in the binary.
+
+pub mod capi;
+pub mod chip_8;
+mod console;
+pub mod crash;
+pub mod font;
+pub mod headless;
+pub mod libretro;
+pub mod opcode;
+pub mod platform;
@@ -0,0 +1,456 @@
+// libretro core, so the interpreter runs inside RetroArch and other libretro
+// frontends. Built as part of the cdylib.
+//
+// API: https://github.com/libretro/libretro-common/blob/master/include/libretro.h
+//
+// The joypad plays the keys most ROMs use for movement and actions, and a
+// keyboard plays the whole keypad with the usual 1234/QWER/ASDF/ZXCV layout.
+//
+// A ROM that hits a bad opcode doesn't take the frontend down with it: the
+// screen freezes, silent, until the game is reset, reloaded or a state
+// loaded.
+
+use crate::chip_8::{Key, Snapshot};
+use crate::crash;
+use crate::headless::HeadlessChip8;
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::Mutex;
+
+const RETRO_API_VERSION: u32 = 1;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: u32 = 10;
+const RETRO_PIXEL_FORMAT_XRGB8888: u32 = 1;
+
+const RETRO_DEVICE_JOYPAD: u32 = 1;
+const RETRO_DEVICE_KEYBOARD: u32 = 3;
+
+const RETRO_REGION_NTSC: u32 = 0;
+const RETRO_MEMORY_SYSTEM_RAM: u32 = 2;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+const SAMPLE_RATE: f64 = 44_100.0;
+const FRAMES_PER_SECOND: f64 = 60.0;
+const TONE: f64 = 440.0;
+const VOLUME: i16 = 0x1000;
+
+const FOREGROUND: u32 = 0xFFFFFF;
+const BACKGROUND: u32 = 0x000000;
+
+// RETRO_DEVICE_ID_JOYPAD_* to Chip-8 keys, WASD style as on the keypad
+const JOYPAD_KEYS: [(u32, u8); 8] = [
+    (4, 0x5), // up
+    (5, 0x8), // down
+    (6, 0x7), // left
+    (7, 0x9), // right
+    (8, 0x6), // a
+    (0, 0x4), // b
+    (9, 0x1), // x
+    (1, 0x2), // y
+];
+
+// RETROK_* codes, which are ASCII for digits and letters, in Chip-8 key order
+const KEYBOARD_KEYS: [u8; 16] = *b"x123qweasdzc4rfv";
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    base_width: u32,
+    base_height: u32,
+    max_width: u32,
+    max_height: u32,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+type EnvironmentFn = extern "C" fn(cmd: u32, data: *mut c_void) -> bool;
+type VideoRefreshFn = extern "C" fn(data: *const c_void, width: u32, height: u32, pitch: usize);
+type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = extern "C" fn();
+type InputStateFn = extern "C" fn(port: u32, device: u32, index: u32, id: u32) -> i16;
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    c8: HeadlessChip8,
+    rom: Vec<u8>,
+    // bytes written every frame, from retro_cheat_set
+    cheats: Vec<(usize, u8)>,
+    video: Vec<u32>,
+    audio: Vec<i16>,
+    phase: f64,
+    crashed: bool,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+impl Core {
+    fn new(rom: Vec<u8>) -> Self {
+        let mut c8 = HeadlessChip8::headless();
+        c8.load(&rom);
+
+        Core {
+            c8,
+            rom,
+            cheats: Vec::new(),
+            video: vec![BACKGROUND; WIDTH * HEIGHT],
+            audio: Vec::new(),
+            phase: 0.0,
+            crashed: false,
+        }
+    }
+
+    fn read_input(&mut self, input_state: InputStateFn) {
+        let mut pressed = [false; 0x10];
+
+        for (id, key) in JOYPAD_KEYS {
+            pressed[key as usize] |= input_state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0;
+        }
+        for (key, &code) in KEYBOARD_KEYS.iter().enumerate() {
+            pressed[key] |= input_state(0, RETRO_DEVICE_KEYBOARD, 0, code as u32) != 0;
+        }
+
+        for (key, &pressed) in pressed.iter().enumerate() {
+            self.c8.set_key(Key::new(key as u8), pressed);
+        }
+    }
+
+    fn render(&mut self) {
+        for (y, &row) in self.c8.framebuffer().iter().enumerate() {
+            for x in 0..WIDTH {
+                self.video[y * WIDTH + x] = if row & (1 << x) != 0 {
+                    FOREGROUND
+                } else {
+                    BACKGROUND
+                };
+            }
+        }
+    }
+
+    // one frame of stereo samples, a square wave while the sound timer runs
+    fn mix(&mut self) {
+        let frames = (SAMPLE_RATE / FRAMES_PER_SECOND) as usize;
+        let active = self.c8.sound_active() && !self.crashed;
+
+        self.audio.clear();
+        for _ in 0..frames {
+            let sample = match (active, self.phase < 0.5) {
+                (false, _) => 0,
+                (true, true) => VOLUME,
+                (true, false) => -VOLUME,
+            };
+            self.audio.extend_from_slice(&[sample, sample]);
+            self.phase = (self.phase + TONE / SAMPLE_RATE) % 1.0;
+        }
+    }
+}
+
+// "ADDR VALUE" or "ADDR:VALUE" in hex, several separated by '+'
+fn parse_cheat(code: &str) -> Option<Vec<(usize, u8)>> {
+    code.split('+')
+        .map(|part| {
+            let (addr, value) = part.trim().split_once([' ', ':'])?;
+            let addr = usize::from_str_radix(addr.trim(), 16).ok()?;
+            let value = u8::from_str_radix(value.trim(), 16).ok()?;
+            (0x200..0x1000).contains(&addr).then_some((addr, value))
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> u32 {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+/// `info` must point to a retro_system_info.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"Chip-8".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"ch8|c8|sc8|xo8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+/// `info` must point to a retro_system_av_info.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: WIDTH as u32,
+            base_height: HEIGHT as u32,
+            max_width: WIDTH as u32,
+            max_height: HEIGHT as u32,
+            aspect_ratio: (WIDTH / HEIGHT) as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FRAMES_PER_SECOND,
+            sample_rate: SAMPLE_RATE,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: u32, _device: u32) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        let (c8, rom) = (&mut core.c8, &core.rom);
+        core.crashed = crash::catch(|| {
+            c8.reset();
+            c8.load(rom);
+        })
+        .is_err();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = CALLBACKS.lock().unwrap();
+    let mut core = CORE.lock().unwrap();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    if let Some(input_state) = callbacks.input_state {
+        core.read_input(input_state);
+    }
+
+    if !core.crashed {
+        let (c8, cheats) = (&mut core.c8, &core.cheats);
+        core.crashed = crash::catch(|| {
+            c8.step_frame();
+            for &(addr, value) in cheats {
+                c8.poke(addr, value);
+            }
+        })
+        .is_err();
+    }
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        core.render();
+        video_refresh(
+            core.video.as_ptr() as *const c_void,
+            WIDTH as u32,
+            HEIGHT as u32,
+            WIDTH * 4,
+        );
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        core.mix();
+        audio_sample_batch(core.audio.as_ptr(), core.audio.len() / 2);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    Snapshot::SIZE
+}
+
+/// # Safety
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core = CORE.lock().unwrap();
+    match core.as_ref() {
+        Some(core) if size >= Snapshot::SIZE => {
+            let bytes = core.c8.snapshot().to_bytes();
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// # Safety
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let bytes = std::slice::from_raw_parts(data as *const u8, size.min(Snapshot::SIZE));
+    let snapshot = match Snapshot::from_bytes(bytes) {
+        Some(snapshot) => snapshot,
+        None => return false,
+    };
+
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) => {
+            let c8 = &mut core.c8;
+            core.crashed = crash::catch(|| c8.restore(&snapshot)).is_err();
+            !core.crashed
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.cheats.clear();
+    }
+}
+
+/// # Safety
+/// `code` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(_index: u32, enabled: bool, code: *const c_char) {
+    if !enabled || code.is_null() {
+        return;
+    }
+
+    let code = CStr::from_ptr(code).to_string_lossy();
+    if let (Some(core), Some(cheats)) = (CORE.lock().unwrap().as_mut(), parse_cheat(&code)) {
+        core.cheats.extend(cheats);
+    }
+}
+
+/// # Safety
+/// `game` must point to a retro_game_info with `size` bytes at `data`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size);
+    if rom.is_empty() || rom.len() > 0x1000 - 0x200 {
+        return false;
+    }
+
+    if let Some(environment) = CALLBACKS.lock().unwrap().environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut u32 as *mut c_void,
+        ) {
+            return false;
+        }
+    }
+
+    *CORE.lock().unwrap() = Some(Core::new(rom.to_vec()));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: u32,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> u32 {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: u32) -> *mut c_void {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => {
+            core.c8.ram_mut().as_mut_ptr() as *mut c_void
+        }
+        _ => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: u32) -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.c8.ram().len(),
+        _ => 0,
+    }
+}
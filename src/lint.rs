@@ -0,0 +1,283 @@
+// Static checks for likely bugs in a ROM.
+//
+// Every reachable path from the entry point is followed while tracking the
+// value of i (when it was set by ANNN) and the depth of the call stack, so
+// that memory accesses and calls can be checked without running the ROM.
+
+use crate::analysis::{self, ENTRY_POINT};
+use crate::opcode;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+const MEMORY_SIZE: usize = 0x1000;
+
+// the interpreter keeps 16 return addresses
+const STACK_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    pub addr: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{:#05x}: {}: {}", self.addr, severity, self.message)
+    }
+}
+
+// value of i known on a path, Unknown once paths with different values meet
+#[derive(Clone, Copy, PartialEq)]
+enum I {
+    Known(usize),
+    Unknown,
+}
+
+impl I {
+    fn merge(self, other: I) -> I {
+        if self == other {
+            self
+        } else {
+            I::Unknown
+        }
+    }
+}
+
+pub fn lint(rom: &[u8]) -> Vec<Finding> {
+    let mut findings = BTreeSet::new();
+    let mut report = |addr: usize, severity: Severity, message: String| {
+        findings.insert(Finding {
+            addr,
+            severity,
+            message,
+        });
+    };
+
+    let mut i_states: HashMap<usize, I> = HashMap::new();
+    let mut depths: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    let mut pending = vec![(ENTRY_POINT, I::Unknown, 0)];
+
+    while let Some((addr, i, depth)) = pending.pop() {
+        let merged = i_states.get(&addr).map_or(i, |&seen| seen.merge(i));
+        let new_depth = depths.entry(addr).or_default().insert(depth);
+        if !new_depth && i_states.get(&addr) == Some(&merged) {
+            continue;
+        }
+        i_states.insert(addr, merged);
+        let i = merged;
+
+        let op = match analysis::read_op(rom, addr) {
+            Some(op) => op,
+            None => {
+                report(
+                    addr,
+                    Severity::Error,
+                    String::from("execution runs past the end of the ROM"),
+                );
+                continue;
+            }
+        };
+
+        let x = ((op >> 8) & 0xF) as usize;
+        let n = (op & 0xF) as usize;
+        let nnn = (op & 0xFFF) as usize;
+
+        if opcode::pattern(op).is_none() {
+            match opcode::platform(op) {
+                Some(platform) => report(
+                    addr,
+                    Severity::Warning,
+                    format!("opcode {:04x} needs the {} platform", op, platform.id()),
+                ),
+                None => {
+                    report(addr, Severity::Error, format!("invalid opcode {:04x}", op));
+                    continue;
+                }
+            }
+        }
+
+        // control flow
+        match op >> 12 {
+            0x1 | 0x2 | 0xB => {
+                let kind = if op >> 12 == 0x2 { "call" } else { "jump" };
+                if nnn < ENTRY_POINT {
+                    report(
+                        addr,
+                        Severity::Error,
+                        format!("{} into the interpreter area at {:#05x}", kind, nnn),
+                    );
+                } else if !nnn.is_multiple_of(2) && op >> 12 != 0xB {
+                    report(
+                        addr,
+                        Severity::Warning,
+                        format!("{} to odd address {:#05x}", kind, nnn),
+                    );
+                }
+            }
+            0x0 if op == 0x00EE && depth == 0 => report(
+                addr,
+                Severity::Error,
+                String::from("return with an empty stack"),
+            ),
+            _ => (),
+        }
+        if op >> 12 == 0x2 && depth == STACK_DEPTH {
+            report(
+                addr,
+                Severity::Error,
+                format!("call could exceed the stack depth of {}", STACK_DEPTH),
+            );
+        }
+
+        // memory access through i
+        if let I::Known(i) = i {
+            let access = match (op >> 12, op & 0xFF) {
+                (0xD, _) => Some(("DXYN reads", n, false)),
+                (0xF, 0x33) => Some(("FX33 writes", 3, true)),
+                (0xF, 0x55) => Some(("FX55 writes", x + 1, true)),
+                (0xF, 0x65) => Some(("FX65 reads", x + 1, false)),
+                _ => None,
+            };
+
+            if let Some((what, len, write)) = access {
+                if i + len > MEMORY_SIZE {
+                    report(
+                        addr,
+                        Severity::Error,
+                        format!("{} past the end of memory (i = {:#05x})", what, i),
+                    );
+                }
+                if write && i < ENTRY_POINT {
+                    report(
+                        addr,
+                        Severity::Error,
+                        format!(
+                            "{} into the font or interpreter area (i = {:#05x})",
+                            what, i
+                        ),
+                    );
+                }
+            }
+        }
+
+        let i = match (op >> 12, op & 0xFF) {
+            (0xA, _) => I::Known(nnn),
+            (0xF, 0x1E | 0x29 | 0x55 | 0x65) => I::Unknown,
+            _ => i,
+        };
+
+        match op >> 12 {
+            0x2 => {
+                if depth < STACK_DEPTH && nnn >= ENTRY_POINT {
+                    pending.push((nnn, i, depth + 1));
+                }
+                // assume the callee returns, without knowing what it did to i
+                pending.push((addr + 2, I::Unknown, depth));
+            }
+            0x0 if op == 0x00EE => (),
+            _ => {
+                for next in analysis::successors(rom, addr, op) {
+                    if next >= ENTRY_POINT {
+                        pending.push((next, i, depth));
+                    }
+                }
+            }
+        }
+    }
+
+    // an address reached at different stack depths means a subroutine was
+    // left without 00EE, or entered without 2NNN. Only the first address of
+    // each such run of code is reported.
+    let unbalanced = |addr: usize| {
+        depths
+            .get(&addr)
+            .is_some_and(|seen| seen.contains(&0) && seen.iter().any(|&depth| depth > 0))
+    };
+    for &addr in depths.keys() {
+        if unbalanced(addr) && !(addr >= 2 && unbalanced(addr - 2)) {
+            report(
+                addr,
+                Severity::Warning,
+                String::from("reached both inside and outside a subroutine, calls and returns may be unbalanced"),
+            );
+        }
+    }
+
+    findings.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(rom: &[u8]) -> Vec<String> {
+        lint(rom).iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn clean_rom_has_no_findings() {
+        // cls, v0 = 1, call a subroutine that draws digit 1 and returns, loop
+        let rom = [
+            0x00, 0xE0, 0x60, 0x01, 0x22, 0x08, 0x12, 0x06, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xEE,
+        ];
+        assert!(findings(&rom).is_empty());
+    }
+
+    #[test]
+    fn reports_bad_control_flow() {
+        assert_eq!(
+            findings(&[0x00, 0xEE]),
+            ["0x200: error: return with an empty stack"]
+        );
+        assert_eq!(
+            findings(&[0x11, 0x00]),
+            ["0x200: error: jump into the interpreter area at 0x100"]
+        );
+        assert_eq!(
+            findings(&[0x60, 0x01]),
+            ["0x202: error: execution runs past the end of the ROM"]
+        );
+        // a subroutine that jumps back instead of returning
+        assert_eq!(
+            findings(&[0x22, 0x04, 0x12, 0x02, 0x12, 0x02]),
+            ["0x202: warning: reached both inside and outside a subroutine, calls and returns may be unbalanced"]
+        );
+    }
+
+    #[test]
+    fn reports_bad_opcodes() {
+        assert_eq!(
+            findings(&[0xF0, 0xFF]),
+            ["0x200: error: invalid opcode f0ff"]
+        );
+        assert_eq!(
+            findings(&[0x00, 0xFF, 0x12, 0x02]),
+            ["0x200: warning: opcode 00ff needs the superchip platform"]
+        );
+    }
+
+    #[test]
+    fn reports_memory_accesses_through_a_known_i() {
+        assert_eq!(
+            findings(&[0xA1, 0x00, 0xF2, 0x55, 0x12, 0x04]),
+            ["0x202: error: FX55 writes into the font or interpreter area (i = 0x100)"]
+        );
+        assert_eq!(
+            findings(&[0xAF, 0xFE, 0xD0, 0x05, 0x12, 0x04]),
+            ["0x202: error: DXYN reads past the end of memory (i = 0xffe)"]
+        );
+        // once i is unknown nothing is reported
+        assert!(findings(&[0xA1, 0x00, 0xF0, 0x1E, 0xF2, 0x55, 0x12, 0x06]).is_empty());
+    }
+}
@@ -1,67 +1,939 @@
 extern crate sdl2;
 
-mod chip_8;
-mod console;
+mod analysis;
+mod bench;
+mod cheats;
+mod coverage;
+mod database;
+mod debugger;
 mod display;
+mod frontend;
+mod hotkeys;
 mod keyboard;
+mod lint;
+mod pacing;
+mod patch;
+mod profile;
+mod rom;
 mod sound;
+mod terminal;
+mod trace;
 
-use chip_8::Chip8;
-use clap::Parser;
-use display::Display;
-use keyboard::Keyboard;
-use sdl2::event::Event;
+use cheats::Cheats;
+use chip_8_interpreter::{chip_8, crash, font, headless, opcode, platform};
+use clap::{Parser, Subcommand};
+use coverage::Coverage;
+use database::Database;
+use debugger::Debugger;
+use font::Font;
+use frontend::{Frontend, HeadlessFrontend, Input, SdlFrontend, StatusDisplay};
+use hotkeys::{Action, Hotkeys};
+use platform::Platform;
+use profile::Profiler;
 use sdl2::keyboard::Scancode;
-use sound::Sound;
-use std::cell::RefCell;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use terminal::TerminalFrontend;
+use trace::Tracer;
+
+// how long a message stays in the window title
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+// how often events are checked while nothing is running
+const IDLE_TIME: Duration = Duration::from_millis(10);
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Config {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(
         short,
         long,
         value_parser,
         value_name = "FILE",
-        help = "ROM file to be loaded into Chip-8"
+        required = true,
+        help = "ROM file to be loaded into Chip-8, a zip archive, or - for standard input"
     )]
-    file: PathBuf,
+    file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "NAME",
+        help = "ROM to load from a zip archive holding more than one"
+    )]
+    rom: Option<String>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        help = "Write an execution trace to FILE"
+    )]
+    trace: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        help = "Profile execution, write a report to FILE and FILE.json on exit"
+    )]
+    profile: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        help = "Write a memory coverage map to FILE on exit (JSON if FILE ends in .json)"
+    )]
+    coverage: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        help = "programs.json of the community CHIP-8 database, searched before the bundled entries"
+    )]
+    database: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "PLATFORM",
+        help = "Platform to emulate, sets the quirks [default: from the ROM database, detected from opcodes, or modernChip8]"
+    )]
+    platform: Option<Platform>,
+
+    #[clap(
+        long = "quirk",
+        value_parser = parse_quirk,
+        value_name = "NAME=BOOL",
+        help = "Override a quirk of the platform, e.g. shift=true, may be repeated"
+    )]
+    quirks: Vec<(String, bool)>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "N",
+        help = "Instructions executed per frame [default: 8, or from the ROM database]"
+    )]
+    speed: Option<usize>,
+
+    #[clap(
+        long,
+        conflicts_with = "speed",
+        help = "Time instructions like a COSMAC VIP instead of a fixed number per frame"
+    )]
+    vip_timing: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "NAME|FILE",
+        help = "Font to use: vip, dream6800, eti660, schip, octo or an 80 byte font file [default: schip]"
+    )]
+    font: Option<String>,
+
+    #[clap(
+        long,
+        value_parser = parse_font_base,
+        value_name = "ADDR",
+        help = "Hex address to place the font at, e.g. 50 [default: 20]"
+    )]
+    font_base: Option<usize>,
+
+    #[clap(
+        long,
+        value_parser = parse_palette,
+        value_name = "BG,FG",
+        help = "Background and foreground colors as hex, e.g. 000000,ffffff"
+    )]
+    palette: Option<(u32, u32)>,
+
+    #[clap(
+        long = "key",
+        value_parser = parse_key,
+        value_name = "NAME=KEY",
+        help = "Bind up, down, left, right, a (space) or b (shift) to a Chip-8 key, e.g. up=5"
+    )]
+    keys: Vec<(String, u8)>,
+
+    #[clap(
+        long = "patch",
+        value_parser,
+        value_name = "FILE",
+        help = "IPS or BPS patch to apply to the ROM, may be repeated"
+    )]
+    patches: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        help = "Cheat file to use, defaults to the ROM file with a .cheats extension"
+    )]
+    cheats: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Start paused in the interactive debugger (F12 breaks into it)"
+    )]
+    debug: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Speed multiple while fast forwarding, 0 runs as fast as possible"
+    )]
+    fast_forward: u32,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..=100),
+        value_name = "PERCENT",
+        default_value_t = 25,
+        help = "Speed while in slow motion"
+    )]
+    slow_motion: u32,
+
+    #[clap(
+        long = "hotkey",
+        value_parser = parse_hotkey,
+        value_name = "ACTION=KEY",
+        help = "Bind pause, advance, reset, hard-reset, fast-forward, slow-motion, cheats or debugger to a key, e.g. pause=P"
+    )]
+    hotkeys: Vec<(Action, String)>,
+
+    #[clap(long, help = "Render without the GPU, using SDL's software renderer")]
+    software_render: bool,
+
+    #[clap(long, help = "Reload the ROM when the file changes")]
+    watch: bool,
+
+    #[clap(
+        long,
+        requires = "watch",
+        help = "Keep registers and memory when reloading, instead of resetting"
+    )]
+    keep_state: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "NAME",
+        default_value = "sdl",
+        help = "Where to run: sdl (a window), terminal or headless (no input or output, as fast as possible)"
+    )]
+    frontend: frontend::Kind,
+
+    #[clap(
+        long,
+        help = "With the terminal frontend, show the screen in reverse video while the sound timer runs, instead of ringing the bell"
+    )]
+    visual_bell: bool,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "N",
+        help = "Quit after running N frames"
+    )]
+    frames: Option<usize>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Report the first cycle where two execution traces diverge
+    TraceDiff {
+        #[clap(value_parser, value_name = "TRACE_A")]
+        a: PathBuf,
+
+        #[clap(value_parser, value_name = "TRACE_B")]
+        b: PathBuf,
+
+        #[clap(
+            short,
+            long,
+            value_parser,
+            default_value_t = 5,
+            help = "Number of cycles to show before and after the divergence"
+        )]
+        context: usize,
+    },
+
+    /// Check a ROM for likely bugs without running it
+    Lint {
+        #[clap(value_parser, value_name = "ROM")]
+        file: PathBuf,
+    },
+
+    /// Measure interpreter speed on built in ROMs, without a window
+    Bench {
+        #[clap(
+            value_parser,
+            value_name = "NAME",
+            help = "Benchmarks to run: alu, sprites or memcpy [default: all]"
+        )]
+        names: Vec<String>,
+
+        #[clap(
+            short = 'n',
+            long,
+            value_parser,
+            default_value_t = 5_000_000,
+            help = "Instructions to execute in each benchmark"
+        )]
+        instructions: usize,
+    },
+
+    /// Work with IPS and BPS patches
+    Patch {
+        #[clap(subcommand)]
+        command: PatchCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PatchCommand {
+    /// Create a patch that turns one ROM into another
+    Create {
+        #[clap(value_parser, value_name = "ORIGINAL")]
+        source: PathBuf,
+
+        #[clap(value_parser, value_name = "MODIFIED")]
+        target: PathBuf,
+
+        #[clap(
+            short,
+            long,
+            value_parser,
+            value_name = "FILE",
+            help = "Patch file to write, BPS if it ends in .bps, otherwise IPS"
+        )]
+        output: PathBuf,
+    },
 }
 
 fn main() {
     let config = Config::parse();
 
-    let sdl_context = sdl2::init().unwrap();
+    match config.command {
+        Some(Command::TraceDiff { a, b, context }) => trace_diff(&a, &b, context),
+        Some(Command::Lint { file }) => lint(&file),
+        Some(Command::Bench {
+            names,
+            instructions,
+        }) => run_benchmarks(&names, instructions),
+        Some(Command::Patch {
+            command:
+                PatchCommand::Create {
+                    source,
+                    target,
+                    output,
+                },
+        }) => patch_create(&source, &target, &output),
+        None => run(config),
+    }
+}
 
-    let event_pump = Rc::new(RefCell::new(sdl_context.event_pump().unwrap()));
+fn trace_diff(a: &Path, b: &Path, context: usize) {
+    let traces = trace::read(a).and_then(|a| Ok((a, trace::read(b)?)));
 
-    let keyboard = Keyboard::new(Rc::clone(&event_pump));
-    let display = Display::new(&sdl_context);
-    let sound = Sound::new(&sdl_context);
-    let mut c8 = Chip8::new(keyboard, display, sound);
+    match traces {
+        Ok((a, b)) => match trace::diff(&a, &b, context) {
+            Some(report) => {
+                print!("{}", report);
+                process::exit(1);
+            }
+            None => println!("traces match ({} cycles)", a.len()),
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+fn lint(file: &Path) {
+    let rom = rom::read(file, None).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    });
+
+    let findings = lint::lint(&rom);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    if findings.is_empty() {
+        println!("no problems found");
+    } else {
+        process::exit(1);
+    }
+}
+
+fn run_benchmarks(names: &[String], instructions: usize) {
+    for name in names {
+        if !bench::BENCHMARKS.iter().any(|b| b.name == name) {
+            eprintln!("error: unknown benchmark '{}'", name);
+            process::exit(2);
+        }
+    }
+
+    for benchmark in &bench::BENCHMARKS {
+        if names.is_empty() || names.iter().any(|name| name == benchmark.name) {
+            let ips = benchmark.run(instructions);
+            println!(
+                "{:<10} {:>8.2} M instructions/s",
+                benchmark.name,
+                ips / 1_000_000.0
+            );
+        }
+    }
+}
+
+fn patch_create(source: &Path, target: &Path, output: &Path) {
+    let read = |path: &Path| {
+        fs::read(path).unwrap_or_else(|e| {
+            eprintln!("error: {}: {}", path.display(), e);
+            process::exit(2);
+        })
+    };
+
+    let patch = patch::create(&read(source), &read(target), output);
+
+    if let Err(e) = fs::write(output, patch) {
+        eprintln!("error: {}: {}", output.display(), e);
+        process::exit(2);
+    }
+}
+
+// applies --patch files in order, then the cheat file patches
+fn apply_patches(
+    mut rom: Vec<u8>,
+    patches: &[PathBuf],
+    cheats: &mut Cheats,
+) -> Result<Vec<u8>, String> {
+    for path in patches {
+        let patch = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        rom = patch::apply(&patch, &rom).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    cheats.patch_rom(&mut rom);
+
+    Ok(rom)
+}
+
+fn parse_quirk(s: &str) -> Result<(String, bool), String> {
+    let (name, value) = s.split_once('=').ok_or("expected NAME=BOOL")?;
+    let value = value.parse().map_err(|_| "expected true or false")?;
+    chip_8::Quirks::default().set(name, value)?;
+
+    Ok((name.to_string(), value))
+}
+
+fn parse_palette(s: &str) -> Result<(u32, u32), String> {
+    let colors = s
+        .split_once(',')
+        .and_then(|(bg, fg)| Some((database::parse_color(bg)?, database::parse_color(fg)?)));
+
+    colors.ok_or_else(|| String::from("expected two hex colors, e.g. 000000,ffffff"))
+}
+
+fn parse_font_base(s: &str) -> Result<usize, String> {
+    let base = usize::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| String::from("expected a hex address"))?;
+
+    // between the stack and the program
+    let last = 0x200 - font::FONT_SIZE;
+    if !(chip_8::FONT_BASE..=last).contains(&base) {
+        return Err(format!(
+            "the font must fit between {:#x} and 0x200, so start from {:#x} to {:#x}",
+            chip_8::FONT_BASE,
+            chip_8::FONT_BASE,
+            last
+        ));
+    }
+
+    Ok(base)
+}
+
+// a built in font by name, otherwise a font file
+fn load_font(name: &str) -> Result<font::FontData, String> {
+    match name.parse::<Font>() {
+        Ok(font) => Ok(*font.data()),
+        Err(e) if !Path::new(name).exists() => Err(e),
+        Err(_) => font::load(Path::new(name)),
+    }
+}
+
+fn parse_key(s: &str) -> Result<(String, u8), String> {
+    let (name, key) = s.split_once('=').ok_or("expected NAME=KEY")?;
+    let key = u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|&key| key <= 0xF)
+        .ok_or("expected a key from 0 to F")?;
+
+    Ok((name.to_string(), key))
+}
+
+fn parse_hotkey(s: &str) -> Result<(Action, String), String> {
+    let (action, key) = s.split_once('=').ok_or("expected ACTION=KEY")?;
+
+    Ok((action.parse()?, key.to_string()))
+}
+
+fn run(config: Config) {
+    let file = config.file.clone().unwrap();
+    let rom = rom::read(&file, config.rom.as_deref()).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    });
+
+    let database = config.database.as_ref().map(|path| {
+        Database::load(path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        })
+    });
+    let entry = database
+        .and_then(|database| database.lookup(&rom))
+        .or_else(|| Database::bundled().lookup(&rom));
+    if let Some(entry) = &entry {
+        if entry.authors.is_empty() {
+            println!("{}", entry.title);
+        } else {
+            println!("{} by {}", entry.title, entry.authors.join(", "));
+        }
+    }
 
-    if let Ok(rom) = fs::read(config.file) {
-        c8.load(&rom);
+    // settings from the command line win over the ROM database
+    let detected = analysis::detect_platform(&rom);
+    let platform = config
+        .platform
+        .or(entry.as_ref().and_then(|e| e.platform))
+        .or(detected)
+        .unwrap_or_default();
+    let mut quirks = match (config.platform, entry.as_ref().and_then(|e| e.quirks)) {
+        (None, Some(quirks)) => quirks,
+        _ => platform.quirks(),
+    };
+    for (name, value) in &config.quirks {
+        quirks.set(name, *value).unwrap();
     }
 
-    loop {
-        for event in event_pump.borrow_mut().poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    scancode: Some(Scancode::Escape),
-                    ..
-                } => process::exit(0),
-                _ => (),
+    if config.platform.is_none() && entry.is_none() && detected.is_some() {
+        println!("platform: {} (detected from opcodes)", platform.id());
+    } else {
+        println!("platform: {}", platform.id());
+    }
+
+    let speed = config.speed.or(entry.as_ref().and_then(|e| e.speed));
+    let palette = config.palette.or(entry.as_ref().and_then(|e| e.palette));
+
+    let font = match &config.font {
+        Some(name) => load_font(name).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }),
+        None => *Font::default().data(),
+    };
+
+    let mut keys: Vec<(String, u8)> = entry.map(|e| e.keys).unwrap_or_default();
+    for (name, key) in &config.keys {
+        keys.retain(|(n, _)| n != name);
+        keys.push((name.clone(), *key));
+    }
+
+    let hotkeys = Hotkeys::new(&config.hotkeys).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    });
+
+    let cheats_path = config
+        .cheats
+        .clone()
+        .or_else(|| Some(file.with_extension("cheats")).filter(|path| path.exists()));
+    let mut cheats = match cheats_path {
+        Some(path) => Cheats::load(&path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }),
+        None => Cheats::new(),
+    };
+    for name in cheats.names() {
+        println!("cheat: {}", name);
+    }
+
+    let rom = apply_patches(rom, &config.patches, &mut cheats).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    });
+
+    if config.watch && file == Path::new("-") {
+        eprintln!("error: --watch needs a ROM file, not standard input");
+        process::exit(2);
+    }
+    if config.debug && file == Path::new("-") {
+        eprintln!(
+            "error: --debug reads commands from standard input, so the ROM must come from a file"
+        );
+        process::exit(2);
+    }
+
+    // settings that only some frontends have
+    if config.debug && config.frontend == frontend::Kind::Terminal {
+        eprintln!("error: --debug reads commands from the terminal, so it can't be used with --frontend terminal");
+        process::exit(2);
+    }
+    if config.visual_bell && config.frontend != frontend::Kind::Terminal {
+        eprintln!("error: --visual-bell needs --frontend terminal");
+        process::exit(2);
+    }
+    // with no input there'd be no way to stop it, other than killing it
+    // before --profile and --coverage are written
+    if config.frontend == frontend::Kind::Headless && config.frames.is_none() && !config.debug {
+        eprintln!("error: --frontend headless needs --frames, or --debug to quit from");
+        process::exit(2);
+    }
+    if config.software_render && config.frontend != frontend::Kind::Sdl {
+        eprintln!("error: --software-render needs --frontend sdl");
+        process::exit(2);
+    }
+
+    let options = frontend::Options {
+        keys,
+        palette,
+        software_render: config.software_render,
+        visual_bell: config.visual_bell,
+    };
+    let setup = Setup {
+        file,
+        rom,
+        cheats,
+        hotkeys,
+        font,
+        quirks,
+        speed,
+    };
+
+    let result = match config.frontend {
+        frontend::Kind::Sdl => run_frontend::<SdlFrontend>(&config, &options, setup),
+        frontend::Kind::Terminal => run_frontend::<TerminalFrontend>(&config, &options, setup),
+        frontend::Kind::Headless => run_frontend::<HeadlessFrontend>(&config, &options, setup),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    }
+}
+
+// the ROM and machine settings, whichever frontend runs them
+struct Setup {
+    file: PathBuf,
+    rom: Vec<u8>,
+    cheats: Cheats,
+    hotkeys: Hotkeys,
+    font: font::FontData,
+    quirks: chip_8::Quirks,
+    speed: Option<usize>,
+}
+
+fn run_frontend<F: Frontend>(
+    config: &Config,
+    options: &frontend::Options,
+    setup: Setup,
+) -> Result<(), String> {
+    let Setup {
+        file,
+        mut rom,
+        mut cheats,
+        hotkeys,
+        font,
+        quirks,
+        speed,
+    } = setup;
+
+    let mut watcher = config.watch.then(|| rom::Watcher::new(&file));
+    // set when the running ROM crashed, until a reload replaces it
+    let mut crashed = false;
+
+    let mut tracer = match &config.trace {
+        Some(path) => Some(Tracer::new(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => None,
+    };
+
+    let mut profiler = config.profile.as_ref().map(|_| Profiler::new());
+
+    let mut coverage = config.coverage.as_ref().map(|_| Coverage::new());
+
+    let mut debugger = config.debug.then(Debugger::new);
+
+    let (mut frontend, mut c8) = F::new(options)?;
+    c8.set_font(&font, config.font_base.unwrap_or(chip_8::FONT_BASE));
+
+    c8.set_quirks(quirks);
+    if let Some(speed) = speed {
+        c8.set_speed(speed);
+    }
+    c8.set_vip_timing(config.vip_timing);
+    c8.load(&rom);
+
+    let mut frame = c8.frame();
+
+    let mut fast_forward = false;
+    let mut slow_motion = false;
+    let mut paused = false;
+    // frame to run to before pausing again, when advancing one frame
+    let mut advance_to: Option<usize> = None;
+    let mut message: Option<(&str, Instant)> = None;
+
+    // frames and instructions per second
+    let mut rates = None;
+    let mut title = String::new();
+    let mut error = None;
+
+    'running: loop {
+        let inputs = match frontend.poll() {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                error = Some(e);
+                break 'running;
+            }
+        };
+
+        let mut changed = false;
+        for input in inputs {
+            match input {
+                Input::Quit | Input::KeyDown(Scancode::Escape) => break 'running,
+                Input::KeyDown(scancode) => {
+                    let action = match hotkeys.action(scancode) {
+                        Some(action) => action,
+                        None => continue,
+                    };
+                    changed = true;
+
+                    match action {
+                        Action::Pause => {
+                            paused = !paused;
+                            advance_to = None;
+                            frontend.print(if paused { "paused" } else { "resumed" });
+                        }
+                        Action::Advance => {
+                            paused = true;
+                            advance_to = Some(c8.frame() + 1);
+                        }
+                        Action::Reset => {
+                            c8.soft_reset();
+                            c8.load(&rom);
+                            if let Some(debugger) = debugger.as_mut() {
+                                debugger.forget();
+                            }
+                            message = Some(("reset", Instant::now()));
+                            frontend.print("reset");
+                        }
+                        Action::HardReset => {
+                            c8.reset();
+                            c8.load(&rom);
+                            if let Some(debugger) = debugger.as_mut() {
+                                debugger.forget();
+                            }
+                            message = Some(("hard reset", Instant::now()));
+                            frontend.print("hard reset");
+                        }
+                        Action::FastForward => fast_forward = true,
+                        Action::SlowMotion => {
+                            slow_motion = !slow_motion;
+                            frontend.print(if slow_motion {
+                                "slow motion on"
+                            } else {
+                                "slow motion off"
+                            });
+                        }
+                        Action::Cheats => {
+                            let enabled = cheats.toggle(&mut c8);
+                            let text = if enabled { "cheats on" } else { "cheats off" };
+                            message = Some((text, Instant::now()));
+                            frontend.print(text);
+                        }
+                        Action::Debugger => {
+                            if let Some(debugger) = debugger.as_mut() {
+                                debugger.pause();
+                            }
+                        }
+                    }
+                }
+                Input::KeyUp(scancode) if hotkeys.action(scancode) == Some(Action::FastForward) => {
+                    fast_forward = false;
+                    changed = true;
+                }
+                Input::KeyUp(_) => (),
             };
         }
 
-        c8.execute();
+        if let Some(measured) = frontend.limiter().rates() {
+            rates = Some(measured);
+            changed = true;
+        }
+
+        // the status shows what the controls did, and the real speed
+        if changed || c8.frame() != frame {
+            if message.is_some_and(|(_, shown)| shown.elapsed() > MESSAGE_TIME) {
+                message = None;
+            }
+
+            let mut status = Vec::new();
+            if paused {
+                status.push(format!("paused at frame {}", c8.frame()));
+            }
+            if fast_forward {
+                status.push(String::from("fast forward"));
+            }
+            if slow_motion {
+                status.push(String::from("slow motion"));
+            }
+            if let Some((text, _)) = message {
+                status.push(String::from(text));
+            }
+            if let (Some((fps, ips)), false) = (rates, paused) {
+                status.push(format!("{:.0} fps, {:.0} ips", fps, ips));
+            }
+
+            let status = status.join(", ");
+            if status != title {
+                c8.display_mut().set_status(&status);
+                title = status;
+            }
+        }
+
+        if watcher.as_mut().is_some_and(|watcher| watcher.changed()) {
+            let reloaded = rom::read(&file, config.rom.as_deref())
+                .and_then(|rom| apply_patches(rom, &config.patches, &mut cheats));
+
+            // a broken build keeps the previous ROM running
+            match reloaded {
+                Ok(reloaded) => {
+                    if !config.keep_state || c8.pc() >= 0x200 + reloaded.len() {
+                        c8.reset();
+                    }
+                    c8.load(&reloaded);
+                    if let Some(debugger) = debugger.as_mut() {
+                        debugger.forget();
+                    }
+                    rom = reloaded;
+                    crashed = false;
+                    frontend.print(&format!("reloaded {}", file.display()));
+                }
+                Err(e) => frontend.print_error(&format!("{}, keeping the running ROM", e)),
+            }
+        }
 
-        c8.sleep();
+        if crashed || (paused && advance_to.is_none()) {
+            thread::sleep(IDLE_TIME);
+            continue;
+        }
+
+        if let Some(debugger) = debugger.as_mut() {
+            if !debugger.before(&mut c8, &mut cheats) {
+                break 'running;
+            }
+        }
+
+        if let Some(tracer) = tracer.as_mut() {
+            tracer.before(&c8);
+        }
+
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.record(c8.frame(), c8.pc(), c8.peek_op());
+        }
+
+        if let Some(coverage) = coverage.as_mut() {
+            coverage.record(&c8);
+        }
+
+        if watcher.is_some() {
+            // with --watch a crashing build waits for the next one instead of
+            // quitting
+            if let Err(message) = crash::catch(|| c8.execute()) {
+                frontend.print_error(&format!(
+                    "{}, waiting for {} to change",
+                    message,
+                    file.display()
+                ));
+                crashed = true;
+                continue;
+            }
+        } else {
+            c8.execute();
+        }
+
+        frontend.limiter().count_instruction();
+
+        // a trace that can't be written is given up on, the ROM keeps running
+        if let Some(Err(e)) = tracer.as_mut().map(|tracer| tracer.after(&c8)) {
+            let path = config.trace.as_ref().unwrap();
+            frontend.print_error(&format!("{}: {}, tracing stopped", path.display(), e));
+            tracer = None;
+        }
+
+        if c8.frame() != frame {
+            frame = c8.frame();
+            cheats.apply(&mut c8);
+
+            if advance_to.is_some_and(|to| frame >= to) {
+                advance_to = None;
+            }
+            if config.frames.is_some_and(|frames| frame >= frames) {
+                break 'running;
+            }
+
+            // the beep would be out of time, so it is muted
+            frontend.set_muted(fast_forward || slow_motion);
+
+            let speed = match (fast_forward, slow_motion) {
+                (true, _) if config.fast_forward == 0 => None,
+                (true, _) => Some(config.fast_forward as f64),
+                (false, true) => Some(config.slow_motion as f64 / 100.0),
+                (false, false) => Some(1.0),
+            };
+            frontend.limiter().end_frame(speed);
+        }
+    }
+
+    // back to the normal terminal before reporting anything
+    drop(frontend);
+
+    if let (Some(tracer), Some(path)) = (tracer, &config.trace) {
+        if let Err(e) = tracer.finish() {
+            eprintln!("error: {}: {}", path.display(), e);
+        }
     }
+
+    if let (Some(profiler), Some(path)) = (profiler.as_mut(), &config.profile) {
+        if let Err(e) = profiler.write(path) {
+            eprintln!("error: {}: {}", path.display(), e);
+        }
+    }
+
+    if let (Some(coverage), Some(path)) = (coverage, &config.coverage) {
+        if let Err(e) = coverage.write(path, c8.ram()) {
+            eprintln!("error: {}: {}", path.display(), e);
+        }
+    }
+
+    error.map_or(Ok(()), Err)
 }
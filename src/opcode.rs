@@ -0,0 +1,80 @@
+use crate::platform::Platform;
+
+// Mnemonic patterns for opcodes, e.g. 0x6A02 -> "6XNN"
+// Opcodes the interpreter does not support have no pattern.
+pub fn pattern(opcode: u16) -> Option<&'static str> {
+    let pattern = match opcode >> 12 {
+        0x0 => match opcode & 0xFFF {
+            0x0E0 => "00E0",
+            0x0EE => "00EE",
+            _ => return None,
+        },
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XNN",
+        0x4 => "4XNN",
+        0x5 if opcode & 0xF == 0 => "5XY0",
+        0x6 => "6XNN",
+        0x7 => "7XNN",
+        0x8 => match opcode & 0xF {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => return None,
+        },
+        0x9 if opcode & 0xF == 0 => "9XY0",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXNN",
+        0xD => "DXYN",
+        0xE => match opcode & 0xFF {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => return None,
+        },
+        0xF => match opcode & 0xFF {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(pattern)
+}
+
+// Platform that introduced an opcode the interpreter doesn't support, e.g.
+// 0x00FF (hires) -> superchip
+pub fn platform(opcode: u16) -> Option<Platform> {
+    let x = (opcode >> 8) & 0xF;
+
+    match (opcode >> 12, opcode & 0xFF) {
+        (0x0, 0xE0 | 0xEE) if x == 0 => None,
+        (0x0, 0xFB..=0xFF) if x == 0 => Some(Platform::Superchip),
+        (0x0, n) if x == 0 && n >> 4 == 0xC => Some(Platform::Superchip),
+        (0x0, n) if x == 0 && n >> 4 == 0xD => Some(Platform::Xochip),
+        // machine code routine on the COSMAC VIP, 0000 is more likely zeroed
+        // memory than a call to address 0
+        (0x0, _) if opcode & 0xFFF != 0 => Some(Platform::OriginalChip8),
+        (0x5, n) if n & 0xF == 0x2 || n & 0xF == 0x3 => Some(Platform::Xochip),
+        (0xD, n) if n & 0xF == 0 => Some(Platform::Superchip),
+        (0xF, 0x00) if x == 0 => Some(Platform::Xochip),
+        (0xF, 0x02) if x == 0 => Some(Platform::Xochip),
+        (0xF, 0x01 | 0x3A) => Some(Platform::Xochip),
+        (0xF, 0x30 | 0x75 | 0x85) => Some(Platform::Superchip),
+        _ => None,
+    }
+}
@@ -0,0 +1,111 @@
+// Frame limiter keeping emulation at 60 frames per second of real time.
+//
+// Each frame moves a target time forward by one frame, and the limiter waits
+// until the clock reaches it. Measuring against the accumulated target
+// rather than sleeping a fixed time per frame means oversleeping in one frame
+// is made up in the next ones, so the speed doesn't depend on the host's
+// sleep granularity or on how long presenting a frame takes.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAMES_PER_SECOND: u32 = 60;
+
+// how far behind the target the host can fall before frames are dropped
+// instead of being run as fast as possible to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// sleeping is only accurate to a millisecond or so, the rest is spun
+const SPIN_TIME: Duration = Duration::from_millis(1);
+
+// how often the measured rates are updated
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct FrameLimiter {
+    // false to only measure, never waiting
+    limited: bool,
+    target: Instant,
+    // frames and instructions run since the last report
+    report_start: Instant,
+    frames: usize,
+    instructions: usize,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        FrameLimiter {
+            limited: true,
+            target: now,
+            report_start: now,
+            frames: 0,
+            instructions: 0,
+        }
+    }
+
+    // runs every frame as fast as possible, whatever the speed
+    pub fn unlimited() -> Self {
+        FrameLimiter {
+            limited: false,
+            ..FrameLimiter::new()
+        }
+    }
+
+    pub fn count_instruction(&mut self) {
+        self.instructions += 1;
+    }
+
+    // call at the end of every emulated frame, `speed` is a multiple of
+    // normal speed and None runs as fast as possible
+    pub fn end_frame(&mut self, speed: Option<f64>) {
+        self.frames += 1;
+
+        let now = Instant::now();
+        let speed = match speed.filter(|_| self.limited) {
+            Some(speed) => speed,
+            None => {
+                self.target = now;
+                return;
+            }
+        };
+
+        let frame = Duration::from_secs(1).div_f64(FRAMES_PER_SECOND as f64 * speed);
+        self.target += frame;
+
+        // after a pause or a stall, start counting again from now
+        if now > self.target + MAX_LAG {
+            self.target = now;
+            return;
+        }
+
+        if let Some(wait) = self.target.checked_duration_since(now) {
+            if wait > SPIN_TIME {
+                thread::sleep(wait - SPIN_TIME);
+            }
+            while Instant::now() < self.target {
+                thread::yield_now();
+            }
+        }
+    }
+
+    // frames and instructions per second, updated once a second
+    pub fn rates(&mut self) -> Option<(f64, f64)> {
+        let elapsed = self.report_start.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let rates = (
+            self.frames as f64 / seconds,
+            self.instructions as f64 / seconds,
+        );
+
+        self.report_start = Instant::now();
+        self.frames = 0;
+        self.instructions = 0;
+
+        Some(rates)
+    }
+}
@@ -0,0 +1,434 @@
+// IPS and BPS patches, the formats ROM hacks and translations ship in.
+//
+// IPS: https://zerosoft.zophar.net/ips.php
+// BPS: https://www.romhacking.net/documents/746/
+
+use std::path::Path;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+// largest record IPS can describe
+const IPS_RECORD_SIZE: usize = 0xFFFF;
+
+// memory left above the interpreter area, no patched ROM can be larger
+const MAX_TARGET_SIZE: usize = 0x1000 - 0x200;
+
+// applies an IPS or BPS patch to a ROM, the format is taken from the patch header
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(patch, rom)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(patch, rom)
+    } else {
+        Err(String::from("not an IPS or BPS patch"))
+    }
+}
+
+// creates a patch turning `source` into `target`, BPS if the output file
+// ends in .bps, otherwise IPS
+pub fn create(source: &[u8], target: &[u8], output: &Path) -> Vec<u8> {
+    if output.extension().is_some_and(|ext| ext == "bps") {
+        create_bps(source, target)
+    } else {
+        create_ips(source, target)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Reader { data, pos }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| String::from("patch is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    // big endian, as used by IPS
+    fn uint(&mut self, n: usize) -> Result<usize, String> {
+        Ok(self
+            .bytes(n)?
+            .iter()
+            .fold(0, |acc, &byte| (acc << 8) | byte as usize))
+    }
+
+    // variable length integer, as used by BPS
+    fn varint(&mut self) -> Result<usize, String> {
+        let bad = || String::from("bad number in patch");
+        let mut data: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let x = self.bytes(1)?[0] as usize;
+            data = (x & 0x7F)
+                .checked_mul(shift)
+                .and_then(|n| data.checked_add(n))
+                .ok_or_else(bad)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(bad)?;
+            data = data.checked_add(shift).ok_or_else(bad)?;
+        }
+    }
+}
+
+fn apply_ips(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+
+    loop {
+        if reader.bytes(3)? == IPS_EOF {
+            break;
+        }
+        reader.pos -= 3;
+
+        let offset = reader.uint(3)?;
+        let (data, size) = match reader.uint(2)? {
+            // run length encoded record
+            0 => {
+                let size = reader.uint(2)?;
+                (vec![reader.bytes(1)?[0]; size], size)
+            }
+            size => (reader.bytes(size)?.to_vec(), size),
+        };
+
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        out[offset..offset + size].copy_from_slice(&data);
+    }
+
+    // optional truncation extension
+    match patch.len() - reader.pos {
+        0 => (),
+        3 => out.truncate(reader.uint(3)?),
+        _ => return Err(String::from("unexpected data after end of IPS patch")),
+    }
+
+    Ok(out)
+}
+
+fn apply_bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_MAGIC.len() + 12 {
+        return Err(String::from("patch is truncated"));
+    }
+
+    let footer = patch.len() - 12;
+    let checksum = |at: usize| Reader::new(patch, at).bytes(4).map(read_u32_le);
+    let (source_crc, target_crc, patch_crc) = (
+        checksum(footer)?,
+        checksum(footer + 4)?,
+        checksum(footer + 8)?,
+    );
+
+    if crc32(&patch[..footer + 8]) != patch_crc {
+        return Err(String::from(
+            "patch checksum mismatch, the patch is corrupt",
+        ));
+    }
+    if crc32(rom) != source_crc {
+        return Err(String::from(
+            "ROM checksum mismatch, the patch is for a different ROM",
+        ));
+    }
+
+    let mut reader = Reader::new(&patch[..footer], BPS_MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        return Err(String::from(
+            "ROM size mismatch, the patch is for a different ROM",
+        ));
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(format!(
+            "patched ROM would be {} bytes, larger than the {} bytes of memory available",
+            target_size, MAX_TARGET_SIZE
+        ));
+    }
+
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    while reader.pos < footer {
+        let command = reader.varint()?;
+        let length = (command >> 2) + 1;
+        if length > target_size - out.len() {
+            return Err(String::from("patch writes past the end of the patched ROM"));
+        }
+
+        match command & 3 {
+            // source read
+            0 => {
+                let bytes = rom
+                    .get(out.len()..out.len() + length)
+                    .ok_or_else(|| String::from("patch reads past the end of the ROM"))?;
+                out.extend_from_slice(bytes);
+            }
+            // target read
+            1 => out.extend_from_slice(reader.bytes(length)?),
+            // source copy
+            2 => {
+                source_offset = relative(source_offset, reader.varint()?)?;
+                let end = source_offset
+                    .checked_add(length)
+                    .ok_or_else(|| String::from("bad length in patch"))?;
+                let bytes = rom
+                    .get(source_offset..end)
+                    .ok_or_else(|| String::from("patch reads past the end of the ROM"))?;
+                out.extend_from_slice(bytes);
+                source_offset = end;
+            }
+            // target copy, may overlap the bytes it is writing
+            _ => {
+                target_offset = relative(target_offset, reader.varint()?)?;
+                for _ in 0..length {
+                    let byte = *out
+                        .get(target_offset)
+                        .ok_or_else(|| String::from("patch reads past the end of the output"))?;
+                    out.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size || crc32(&out) != target_crc {
+        return Err(String::from("patched ROM checksum mismatch"));
+    }
+
+    Ok(out)
+}
+
+// applies a signed BPS offset, the low bit is the sign
+fn relative(offset: usize, data: usize) -> Result<usize, String> {
+    let delta = data >> 1;
+    let result = if data & 1 != 0 {
+        offset.checked_sub(delta)
+    } else {
+        offset.checked_add(delta)
+    };
+
+    result.ok_or_else(|| String::from("bad offset in patch"))
+}
+
+fn create_ips(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = IPS_MAGIC.to_vec();
+    let mut pos = 0;
+
+    while pos < target.len() {
+        if source.get(pos) == Some(&target[pos]) {
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        while pos < target.len()
+            && pos - start < IPS_RECORD_SIZE
+            && source.get(pos) != Some(&target[pos])
+        {
+            pos += 1;
+        }
+
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((pos - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&target[start..pos]);
+    }
+
+    patch.extend_from_slice(IPS_EOF);
+    if target.len() < source.len() {
+        patch.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+
+    patch
+}
+
+fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    write_varint(&mut patch, source.len());
+    write_varint(&mut patch, target.len());
+    write_varint(&mut patch, 0);
+
+    let matches = |pos: usize| source.get(pos) == Some(&target[pos]);
+    let mut pos = 0;
+
+    while pos < target.len() {
+        let start = pos;
+        let same = matches(pos);
+        while pos < target.len() && matches(pos) == same {
+            pos += 1;
+        }
+
+        let command = ((pos - start - 1) << 2) | if same { 0 } else { 1 };
+        write_varint(&mut patch, command);
+        if !same {
+            patch.extend_from_slice(&target[start..pos]);
+        }
+    }
+
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc = crc32(&patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+    patch
+}
+
+fn write_varint(out: &mut Vec<u8>, mut data: usize) {
+    loop {
+        let x = (data & 0x7F) as u8;
+        data >>= 7;
+        if data == 0 {
+            out.push(0x80 | x);
+            return;
+        }
+        out.push(x);
+        data -= 1;
+    }
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"\x00\xE0\xA2\x2A\x60\x0C\x61\x08\xD0\x1F\x12\x0A";
+
+    fn targets() -> Vec<Vec<u8>> {
+        let mut changed = SOURCE.to_vec();
+        changed[3] = 0x30;
+        changed[10] = 0x13;
+
+        let mut longer = changed.clone();
+        longer.extend_from_slice(&[0xFF; 300]);
+
+        vec![changed, longer, SOURCE[..5].to_vec(), SOURCE.to_vec()]
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn ips_round_trips() {
+        for target in targets() {
+            let patch = create(SOURCE, &target, Path::new("out.ips"));
+            assert!(patch.starts_with(IPS_MAGIC));
+            assert_eq!(apply(&patch, SOURCE).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn bps_round_trips() {
+        for target in targets() {
+            let patch = create(SOURCE, &target, Path::new("out.bps"));
+            assert!(patch.starts_with(BPS_MAGIC));
+            assert_eq!(apply(&patch, SOURCE).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn ips_applies_run_length_records() {
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x04\xAAEOF";
+        assert_eq!(
+            apply(patch, &[1, 2, 3]).unwrap(),
+            [1, 2, 0xAA, 0xAA, 0xAA, 0xAA]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_patches() {
+        assert!(apply(b"NOT A PATCH", SOURCE).is_err());
+        // a record cut short, and no EOF
+        assert!(apply(b"PATCH\x00\x00\x02\x00\x04\xAA", SOURCE).is_err());
+        assert!(apply(b"PATCH\x00\x00\x02\x00\x01\xAAEOF\x00", SOURCE).is_err());
+
+        let target = &targets()[0];
+        let patch = create(SOURCE, target, Path::new("out.bps"));
+        assert!(apply(&patch, target).is_err());
+        assert!(apply(&patch[..8], SOURCE).is_err());
+
+        let mut corrupt = patch.clone();
+        corrupt[6] ^= 1;
+        assert!(apply(&corrupt, SOURCE).is_err());
+    }
+
+    // a BPS patch with valid checksums, a source size of SOURCE and the given
+    // header and commands
+    fn bps(target_size: &[u8], commands: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        write_varint(&mut patch, SOURCE.len());
+        patch.extend_from_slice(target_size);
+        write_varint(&mut patch, 0);
+        patch.extend_from_slice(commands);
+        patch.extend_from_slice(&crc32(SOURCE).to_le_bytes());
+        patch.extend_from_slice(&0u32.to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn rejects_oversized_bps_targets() {
+        let mut huge = Vec::new();
+        write_varint(&mut huge, 1 << 50);
+        assert_eq!(
+            apply(&bps(&huge, &[]), SOURCE).unwrap_err(),
+            "patched ROM would be 1125899906842624 bytes, larger than the 3584 bytes of memory available"
+        );
+
+        // a number too large for usize
+        assert_eq!(
+            apply(&bps(&[0; 12], &[]), SOURCE).unwrap_err(),
+            "bad number in patch"
+        );
+
+        // one byte, then a target copy far longer than the 4 bytes claimed
+        let mut commands = vec![0x81, 0xAA];
+        write_varint(&mut commands, (1 << 40) << 2 | 3);
+        write_varint(&mut commands, 0);
+        assert_eq!(
+            apply(&bps(&[0x84], &commands), SOURCE).unwrap_err(),
+            "patch writes past the end of the patched ROM"
+        );
+    }
+}
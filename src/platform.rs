@@ -0,0 +1,85 @@
+use crate::chip_8::Quirks;
+use std::str::FromStr;
+
+// Chip-8 platforms, with the ids used by the community CHIP-8 database
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Platform {
+    OriginalChip8,
+    HybridVip,
+    #[default]
+    ModernChip8,
+    Chip48,
+    Superchip1,
+    Superchip,
+    Megachip8,
+    Xochip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 8] = [
+        Platform::OriginalChip8,
+        Platform::HybridVip,
+        Platform::ModernChip8,
+        Platform::Chip48,
+        Platform::Superchip1,
+        Platform::Superchip,
+        Platform::Megachip8,
+        Platform::Xochip,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Platform::OriginalChip8 => "originalChip8",
+            Platform::HybridVip => "hybridVIP",
+            Platform::ModernChip8 => "modernChip8",
+            Platform::Chip48 => "chip48",
+            Platform::Superchip1 => "superchip1",
+            Platform::Superchip => "superchip",
+            Platform::Megachip8 => "megachip8",
+            Platform::Xochip => "xochip",
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        let none = Quirks::default();
+
+        match self {
+            Platform::OriginalChip8 | Platform::HybridVip => Quirks {
+                logic: true,
+                ..none
+            },
+            Platform::ModernChip8 => none,
+            Platform::Chip48 | Platform::Superchip1 => Quirks {
+                shift: true,
+                memory_increment_by_x: true,
+                jump: true,
+                ..none
+            },
+            Platform::Superchip | Platform::Megachip8 => Quirks {
+                shift: true,
+                memory_leave_i_unchanged: true,
+                jump: true,
+                ..none
+            },
+            Platform::Xochip => Quirks { wrap: true, ..none },
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .into_iter()
+            .find(|platform| platform.id() == id)
+            .ok_or_else(|| {
+                let ids: Vec<&str> = Platform::ALL.iter().map(|p| p.id()).collect();
+                format!(
+                    "unknown platform '{}', expected one of {}",
+                    id,
+                    ids.join(", ")
+                )
+            })
+    }
+}
@@ -0,0 +1,207 @@
+// Execution profiler, counts executed opcodes and addresses over a whole run.
+//
+// Frames are timer ticks (see Chip8::frame). A frame counts as spent waiting
+// on a key when it executed nothing but FX0A, and as a busy-wait timer loop
+// when it stayed within a few addresses polling the delay timer (FX07) without
+// drawing or writing memory.
+
+use crate::opcode;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// addresses listed in the report, most executed first
+const HOT_ADDRESSES: usize = 20;
+
+// largest number of distinct addresses a busy-wait loop may span
+const BUSY_WAIT_SPAN: usize = 4;
+
+#[derive(Default)]
+pub struct Profiler {
+    instructions: usize,
+    opcodes: HashMap<&'static str, usize>,
+    addresses: HashMap<usize, usize>,
+    frames: Vec<usize>,
+    key_wait_frames: usize,
+    timer_wait_frames: usize,
+    frame: Option<usize>,
+    frame_ops: Vec<(usize, u16)>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    instructions: usize,
+    frames: usize,
+    opcodes: BTreeMap<&'static str, usize>,
+    hot_addresses: Vec<HotAddress>,
+    instructions_per_frame: FrameStats,
+    key_wait_frames: usize,
+    timer_wait_frames: usize,
+}
+
+#[derive(Serialize)]
+struct HotAddress {
+    address: usize,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct FrameStats {
+    min: usize,
+    max: usize,
+    mean: f64,
+    histogram: BTreeMap<usize, usize>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    // call with the frame, pc and opcode right before Chip8::execute
+    pub fn record(&mut self, frame: usize, pc: usize, op: u16) {
+        if self.frame != Some(frame) {
+            self.end_frame();
+            self.frame = Some(frame);
+        }
+
+        self.instructions += 1;
+        *self
+            .opcodes
+            .entry(opcode::pattern(op).unwrap_or("invalid"))
+            .or_insert(0) += 1;
+        *self.addresses.entry(pc).or_insert(0) += 1;
+        self.frame_ops.push((pc, op));
+    }
+
+    fn end_frame(&mut self) {
+        if self.frame_ops.is_empty() {
+            return;
+        }
+
+        let patterns: Vec<Option<&str>> = self
+            .frame_ops
+            .iter()
+            .map(|&(_, op)| opcode::pattern(op))
+            .collect();
+        let span = self
+            .frame_ops
+            .iter()
+            .map(|&(pc, _)| pc)
+            .collect::<HashSet<usize>>()
+            .len();
+
+        if patterns.iter().all(|&p| p == Some("FX0A")) {
+            self.key_wait_frames += 1;
+        } else if span <= BUSY_WAIT_SPAN
+            && patterns.contains(&Some("FX07"))
+            && !patterns
+                .iter()
+                .any(|p| matches!(p, Some("00E0" | "DXYN" | "FX33" | "FX55")))
+        {
+            self.timer_wait_frames += 1;
+        }
+
+        self.frames.push(self.frame_ops.len());
+        self.frame_ops.clear();
+    }
+
+    fn report(&mut self) -> Report {
+        self.end_frame();
+
+        let mut hot_addresses: Vec<HotAddress> = self
+            .addresses
+            .iter()
+            .map(|(&address, &count)| HotAddress { address, count })
+            .collect();
+        hot_addresses.sort_by(|a, b| b.count.cmp(&a.count).then(a.address.cmp(&b.address)));
+        hot_addresses.truncate(HOT_ADDRESSES);
+
+        let mut histogram = BTreeMap::new();
+        for &n in &self.frames {
+            *histogram.entry(n).or_insert(0) += 1;
+        }
+
+        Report {
+            instructions: self.instructions,
+            frames: self.frames.len(),
+            opcodes: self.opcodes.iter().map(|(&k, &v)| (k, v)).collect(),
+            hot_addresses,
+            instructions_per_frame: FrameStats {
+                min: self.frames.iter().copied().min().unwrap_or(0),
+                max: self.frames.iter().copied().max().unwrap_or(0),
+                mean: if self.frames.is_empty() {
+                    0.0
+                } else {
+                    self.instructions as f64 / self.frames.len() as f64
+                },
+                histogram,
+            },
+            key_wait_frames: self.key_wait_frames,
+            timer_wait_frames: self.timer_wait_frames,
+        }
+    }
+
+    // writes a text report to `path` and a JSON report next to it
+    pub fn write(&mut self, path: &Path) -> io::Result<()> {
+        let report = self.report();
+
+        let mut json_path = path.as_os_str().to_owned();
+        json_path.push(".json");
+        fs::write(&json_path, serde_json::to_string_pretty(&report)?)?;
+
+        fs::write(path, report.to_string())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |n: usize| 100.0 * n as f64 / self.instructions.max(1) as f64;
+
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "frames: {}", self.frames)?;
+
+        writeln!(f, "\nopcodes:")?;
+        let mut opcodes: Vec<(&&str, &usize)> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (pattern, &count) in opcodes {
+            writeln!(f, "  {:<8} {:>12} {:>6.2}%", pattern, count, percent(count))?;
+        }
+
+        writeln!(f, "\nhot addresses:")?;
+        for hot in &self.hot_addresses {
+            writeln!(
+                f,
+                "  {:#05x}    {:>12} {:>6.2}%",
+                hot.address,
+                hot.count,
+                percent(hot.count)
+            )?;
+        }
+
+        let stats = &self.instructions_per_frame;
+        writeln!(f, "\ninstructions per frame:")?;
+        writeln!(
+            f,
+            "  min {}, max {}, mean {:.2}",
+            stats.min, stats.max, stats.mean
+        )?;
+        for (n, frames) in &stats.histogram {
+            writeln!(f, "  {:>8} {:>12} frames", n, frames)?;
+        }
+
+        writeln!(
+            f,
+            "\nframes waiting on a key (FX0A): {}",
+            self.key_wait_frames
+        )?;
+        writeln!(
+            f,
+            "frames in busy-wait timer loops: {}",
+            self.timer_wait_frames
+        )
+    }
+}
@@ -0,0 +1,161 @@
+// Reading ROMs from a file, a zip archive or standard input.
+
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use zip::ZipArchive;
+
+// a file name of "-" reads from standard input
+const STDIN: &str = "-";
+
+// a local file header, or the end of an archive with no files in it
+const ZIP_MAGIC: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06"];
+
+// memory left above the interpreter area
+const MAX_SIZE: usize = 0x1000 - 0x200;
+
+// extensions used for ROMs, to tell them apart from readmes in an archive
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "sc8", "xo8", "c8x"];
+
+// Reads a ROM. Zip archives are recognised by their header, `name` picks a
+// ROM inside one and can be left out when the archive holds a single ROM.
+pub fn read(path: &Path, name: Option<&str>) -> Result<Vec<u8>, String> {
+    let stdin = path == Path::new(STDIN);
+    let source = if stdin {
+        String::from("standard input")
+    } else {
+        path.display().to_string()
+    };
+
+    let data = if stdin {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data).map(|_| data)
+    } else {
+        fs::read(path)
+    };
+    let data = data.map_err(|e| format!("{}: {}", source, e))?;
+
+    let rom = if ZIP_MAGIC.iter().any(|magic| data.starts_with(magic)) {
+        extract(data, name).map_err(|e| format!("{}: {}", source, e))?
+    } else if let Some(name) = name {
+        return Err(format!(
+            "{}: not a zip archive, cannot pick '{}' from it",
+            source, name
+        ));
+    } else {
+        data
+    };
+
+    if rom.is_empty() {
+        return Err(format!("{}: ROM is empty", source));
+    }
+    if rom.len() > MAX_SIZE {
+        return Err(format!(
+            "{}: ROM is {} bytes, larger than the {} bytes of memory available",
+            source,
+            rom.len(),
+            MAX_SIZE
+        ));
+    }
+
+    Ok(rom)
+}
+
+fn extract(data: Vec<u8>, name: Option<&str>) -> Result<Vec<u8>, String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+
+    let files: Vec<String> = archive
+        .file_names()
+        .filter(|file| !file.ends_with('/'))
+        .map(String::from)
+        .collect();
+
+    let chosen = match name {
+        // either the full path inside the archive or just the file name
+        Some(name) => files
+            .iter()
+            .find(|file| *file == name || base_name(file) == name)
+            .ok_or_else(|| format!("no '{}' in archive, it has {}", name, files.join(", ")))?,
+        None => {
+            let roms: Vec<&String> = files.iter().filter(|file| is_rom(file)).collect();
+            match (roms.as_slice(), files.as_slice()) {
+                ([rom], _) => rom,
+                ([], [file]) => file,
+                ([], []) => return Err(String::from("archive is empty")),
+                ([], _) => {
+                    return Err(format!(
+                        "no ROM found in archive, pick one of {} with --rom",
+                        files.join(", ")
+                    ))
+                }
+                (roms, _) => {
+                    let roms: Vec<&str> = roms.iter().map(|rom| rom.as_str()).collect();
+                    return Err(format!(
+                        "archive has several ROMs, pick one of {} with --rom",
+                        roms.join(", ")
+                    ));
+                }
+            }
+        }
+    };
+
+    let mut file = archive.by_name(chosen).map_err(|e| e.to_string())?;
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom)
+        .map_err(|e| format!("{}: {}", chosen, e))?;
+
+    Ok(rom)
+}
+
+fn base_name(file: &str) -> &str {
+    file.rsplit('/').next().unwrap_or(file)
+}
+
+fn is_rom(file: &str) -> bool {
+    Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// how often a watched file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Notices when a ROM file is rewritten, by polling its modification time.
+pub struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Self {
+        Watcher {
+            path: path.to_path_buf(),
+            modified: Watcher::modified(path),
+            checked: Instant::now(),
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    // true once for every change, cheap enough to call every cycle
+    pub fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+
+        // a missing file is usually a build in progress, wait for it to return
+        let modified = Watcher::modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+
+        true
+    }
+}
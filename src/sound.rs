@@ -4,19 +4,26 @@ use sdl2::{
 };
 
 use crate::chip_8::SoundInterface;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    muted: Arc<AtomicBool>,
 }
 
 impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
+        let muted = self.muted.load(Ordering::Relaxed);
+
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
+            *x = if muted {
+                0.0
+            } else if self.phase <= 0.5 {
                 self.volume
             } else {
                 -self.volume
@@ -28,6 +35,7 @@ impl AudioCallback for SquareWave {
 
 pub struct Sound {
     device: AudioDevice<SquareWave>,
+    muted: Arc<AtomicBool>,
 }
 
 impl Sound {
@@ -40,15 +48,23 @@ impl Sound {
             samples: None,
         };
 
+        let muted = Arc::new(AtomicBool::new(false));
+
         let device = audio_subsystem
             .open_playback(None, &desired_spec, |spec| SquareWave {
                 phase_inc: 440.0 / spec.freq as f32,
                 phase: 0.0,
                 volume: 0.25,
+                muted: Arc::clone(&muted),
             })
             .unwrap();
 
-        Sound { device }
+        Sound { device, muted }
+    }
+
+    // silences the beep while set, without changing when it starts and stops
+    pub fn mute_switch(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.muted)
     }
 }
 
@@ -0,0 +1,409 @@
+// Terminal frontend, for playing over SSH without a display server.
+//
+// The screen is drawn with half block characters, two pixels to a character
+// cell, so it takes 64 columns and 16 rows, with a status line below.
+//
+// Terminals report key presses, and repeats while a key is held, but not
+// releases unless they support the kitty keyboard protocol. Without it a key
+// counts as held until no press or repeat has come for HOLD_TIME.
+
+use crate::chip_8::{BitMap, Chip8, DisplayInterface, SoundInterface};
+use crate::crash;
+use crate::frontend::{Frontend, Input, Machine, Options, StatusDisplay};
+use crate::keyboard::{Keyboard, PressedKeys};
+use crate::pacing::FrameLimiter;
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, ClearType},
+};
+use sdl2::keyboard::Scancode;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::panic::{self, PanicHookInfo};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// longer than the gap between key repeats, shorter than the delay before
+// they start, so holding a key may let go of it briefly
+const HOLD_TIME: Duration = Duration::from_millis(150);
+
+// reverse video on and off, for the visual bell
+const FLASH_ON: &str = "\x1b[?5h";
+const FLASH_OFF: &str = "\x1b[?5l";
+
+type PanicHook = dyn Fn(&PanicHookInfo) + Sync + Send;
+
+// The first error writing to the terminal, e.g. once an SSH session drops.
+// The display and sound can't return it, so they leave it here for the next
+// poll to report, and write nothing more.
+type WriteError = Rc<RefCell<Option<io::Error>>>;
+
+#[derive(Default)]
+pub struct HeldKeys {
+    // when each key was last pressed or repeated, None when the terminal
+    // reports its release
+    held: Vec<(Scancode, Option<Instant>)>,
+}
+
+impl HeldKeys {
+    fn expired(pressed: Option<Instant>) -> bool {
+        pressed.is_some_and(|pressed| pressed.elapsed() > HOLD_TIME)
+    }
+}
+
+impl PressedKeys for Rc<RefCell<HeldKeys>> {
+    fn is_pressed(&self, scancode: Scancode) -> bool {
+        self.borrow()
+            .held
+            .iter()
+            .any(|&(held, pressed)| held == scancode && !HeldKeys::expired(pressed))
+    }
+
+    fn pressed(&self) -> Vec<Scancode> {
+        self.borrow()
+            .held
+            .iter()
+            .filter(|&&(_, pressed)| !HeldKeys::expired(pressed))
+            .map(|&(scancode, _)| scancode)
+            .collect()
+    }
+}
+
+// raw mode on the alternate screen, until dropped
+pub struct TerminalFrontend {
+    // shared with the keyboard, which reads the keys held down from it
+    keys: Rc<RefCell<HeldKeys>>,
+    // whether the terminal reports key releases
+    releases: bool,
+    muted: Rc<Cell<bool>>,
+    limiter: FrameLimiter,
+    write_error: WriteError,
+    // the panic hook from before ours, put back when dropped
+    hook: Arc<PanicHook>,
+}
+
+impl Frontend for TerminalFrontend {
+    type Keyboard = Keyboard<Rc<RefCell<HeldKeys>>>;
+    type Display = TerminalDisplay;
+    type Sound = TerminalSound;
+
+    fn new(options: &Options) -> Result<(Self, Machine<Self>), String> {
+        let keys = Rc::new(RefCell::new(HeldKeys::default()));
+
+        // warnings first, they would be lost with the alternate screen
+        let mut keyboard = Keyboard::new(Rc::clone(&keys));
+        for name in keyboard.set_game_keys(&options.keys) {
+            eprintln!("warning: unknown game key '{}'", name);
+        }
+
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        // put the terminal back before printing a panic, for the same reason,
+        // unless it's caught and the frontend keeps going
+        crash::install_hook();
+        let hook: Arc<PanicHook> = Arc::from(panic::take_hook());
+        let previous = Arc::clone(&hook);
+        panic::set_hook(Box::new(move |info| {
+            if !crash::catching() {
+                restore(releases);
+            }
+            previous(info);
+        }));
+
+        let setup = || -> io::Result<()> {
+            terminal::enable_raw_mode()?;
+            execute!(
+                io::stdout(),
+                terminal::EnterAlternateScreen,
+                cursor::Hide,
+                terminal::Clear(ClearType::All)
+            )?;
+            if releases {
+                execute!(
+                    io::stdout(),
+                    PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+                )?;
+            }
+            Ok(())
+        };
+        setup().map_err(|e| format!("terminal: {}", e))?;
+
+        let write_error = WriteError::default();
+
+        let mut display = TerminalDisplay::new(Rc::clone(&write_error));
+        if let Some((background, foreground)) = options.palette {
+            display.set_palette(background, foreground);
+        }
+
+        let muted = Rc::new(Cell::new(false));
+        let sound = TerminalSound::new(
+            options.visual_bell,
+            Rc::clone(&muted),
+            Rc::clone(&write_error),
+        );
+
+        let frontend = TerminalFrontend {
+            keys,
+            releases,
+            muted,
+            limiter: FrameLimiter::new(),
+            write_error,
+            hook,
+        };
+
+        Ok((frontend, Chip8::new(keyboard, display, sound)))
+    }
+
+    fn poll(&mut self) -> Result<Vec<Input>, String> {
+        let error = |e: &io::Error| format!("terminal: {}", e);
+        if let Some(e) = &*self.write_error.borrow() {
+            return Err(error(e));
+        }
+
+        let mut inputs = Vec::new();
+        let mut keys = self.keys.borrow_mut();
+
+        while event::poll(Duration::ZERO).map_err(|e| error(&e))? {
+            let key = match event::read().map_err(|e| error(&e))? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                inputs.push(Input::Quit);
+                continue;
+            }
+            let scancode = match to_scancode(key.code) {
+                Some(scancode) => scancode,
+                None => continue,
+            };
+
+            let held = keys.held.iter().position(|&(held, _)| held == scancode);
+            match (key.kind, held) {
+                (KeyEventKind::Release, Some(at)) => {
+                    keys.held.remove(at);
+                    inputs.push(Input::KeyUp(scancode));
+                }
+                (KeyEventKind::Release, None) => (),
+                (_, Some(at)) => keys.held[at].1 = (!self.releases).then(Instant::now),
+                (_, None) => {
+                    keys.held
+                        .push((scancode, (!self.releases).then(Instant::now)));
+                    inputs.push(Input::KeyDown(scancode));
+                }
+            }
+        }
+
+        keys.held.retain(|&(scancode, pressed)| {
+            let expired = HeldKeys::expired(pressed);
+            if expired {
+                inputs.push(Input::KeyUp(scancode));
+            }
+            !expired
+        });
+
+        Ok(inputs)
+    }
+
+    fn limiter(&mut self) -> &mut FrameLimiter {
+        &mut self.limiter
+    }
+
+    fn set_muted(&mut self, muted: bool) {
+        self.muted.set(muted);
+    }
+
+    // on the line below the status, printing would scroll the screen
+    fn print(&mut self, line: &str) {
+        write(&self.write_error, |stdout| {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, (HEIGHT / 2 + 1) as u16),
+                terminal::Clear(ClearType::UntilNewLine),
+                Print(line)
+            )
+        });
+    }
+
+    fn print_error(&mut self, line: &str) {
+        self.print(&format!("error: {}", line));
+    }
+}
+
+impl Drop for TerminalFrontend {
+    fn drop(&mut self) {
+        restore(self.releases);
+
+        // hooks can't be changed while panicking, the process is on its way
+        // out then anyway
+        if !thread::panicking() {
+            let hook = Arc::clone(&self.hook);
+            panic::set_hook(Box::new(move |info| hook(info)));
+        }
+    }
+}
+
+// writes and flushes stdout, unless writing has already failed
+fn write(error: &WriteError, f: impl FnOnce(&mut io::Stdout) -> io::Result<()>) {
+    if error.borrow().is_some() {
+        return;
+    }
+
+    let mut stdout = io::stdout();
+    if let Err(e) = f(&mut stdout).and_then(|()| stdout.flush()) {
+        *error.borrow_mut() = Some(e);
+    }
+}
+
+fn restore(releases: bool) {
+    let mut stdout = io::stdout();
+    if releases {
+        let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(
+        stdout,
+        Print(FLASH_OFF),
+        ResetColor,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    );
+    let _ = terminal::disable_raw_mode();
+}
+
+// the same names SDL uses, so keys and hotkeys are bound the same way
+fn to_scancode(code: KeyCode) -> Option<Scancode> {
+    match code {
+        KeyCode::Char(' ') => Some(Scancode::Space),
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() => {
+            Scancode::from_name(&c.to_ascii_uppercase().to_string())
+        }
+        KeyCode::Up => Some(Scancode::Up),
+        KeyCode::Down => Some(Scancode::Down),
+        KeyCode::Left => Some(Scancode::Left),
+        KeyCode::Right => Some(Scancode::Right),
+        KeyCode::Tab => Some(Scancode::Tab),
+        KeyCode::Enter => Some(Scancode::Return),
+        KeyCode::Esc => Some(Scancode::Escape),
+        KeyCode::F(n) => Scancode::from_name(&format!("F{}", n)),
+        _ => None,
+    }
+}
+
+pub struct TerminalDisplay {
+    // background and foreground, the terminal's own colors if None
+    palette: Option<(Color, Color)>,
+    write_error: WriteError,
+}
+
+impl TerminalDisplay {
+    pub fn new(write_error: WriteError) -> Self {
+        TerminalDisplay {
+            palette: None,
+            write_error,
+        }
+    }
+
+    // colors as 0xRRGGBB
+    pub fn set_palette(&mut self, background: u32, foreground: u32) {
+        let rgb = |c: u32| Color::Rgb {
+            r: (c >> 16) as u8,
+            g: (c >> 8) as u8,
+            b: c as u8,
+        };
+        self.palette = Some((rgb(background), rgb(foreground)));
+    }
+}
+
+impl StatusDisplay for TerminalDisplay {
+    // shows a status on the line below the screen
+    fn set_status(&mut self, status: &str) {
+        write(&self.write_error, |stdout| {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, (HEIGHT / 2) as u16),
+                terminal::Clear(ClearType::UntilNewLine),
+                Print(status)
+            )
+        });
+    }
+}
+
+impl DisplayInterface for TerminalDisplay {
+    fn draw(&mut self, bitmap: &BitMap) {
+        let palette = self.palette;
+
+        write(&self.write_error, |stdout| {
+            if let Some((background, foreground)) = palette {
+                queue!(
+                    stdout,
+                    SetBackgroundColor(background),
+                    SetForegroundColor(foreground)
+                )?;
+            }
+
+            for (y, rows) in bitmap.chunks(2).enumerate() {
+                let line: String = (0..WIDTH)
+                    .map(
+                        |x| match (rows[0] & (1 << x) != 0, rows[1] & (1 << x) != 0) {
+                            (false, false) => ' ',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (true, true) => '█',
+                        },
+                    )
+                    .collect();
+                queue!(stdout, cursor::MoveTo(0, y as u16), Print(line))?;
+            }
+
+            queue!(stdout, ResetColor)
+        });
+    }
+}
+
+// rings the terminal bell when the sound timer starts, or with `flash` shows
+// the screen in reverse video while it runs
+pub struct TerminalSound {
+    flash: bool,
+    muted: Rc<Cell<bool>>,
+    write_error: WriteError,
+}
+
+impl TerminalSound {
+    pub fn new(flash: bool, muted: Rc<Cell<bool>>, write_error: WriteError) -> Self {
+        TerminalSound {
+            flash,
+            muted,
+            write_error,
+        }
+    }
+
+    fn write(&self, s: &str) {
+        write(&self.write_error, |stdout| stdout.write_all(s.as_bytes()));
+    }
+}
+
+impl SoundInterface for TerminalSound {
+    fn start(&self) {
+        if self.muted.get() {
+            return;
+        }
+        self.write(if self.flash { FLASH_ON } else { "\x07" });
+    }
+
+    fn stop(&self) {
+        if self.flash {
+            self.write(FLASH_OFF);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Execution traces, one line per executed instruction.
+//
+// The format is plain text so that other emulators can produce it with a few
+// lines of code. Blank lines and lines starting with '#' are ignored, every
+// other line is one instruction, so the n-th instruction line is cycle n.
+// Each line is a whitespace separated list of key=value fields, values are hex
+// with an optional 0x prefix:
+//
+//   pc=0200 op=6a02 i=0000 v=00000000000000000000020000000000 dt=00 st=00 m=
+//
+//   pc   address of the executed instruction
+//   op   the executed opcode
+//   i    value of i after the instruction
+//   v    v[0] through v[F] after the instruction, 2 hex digits each
+//   dt   delay timer after the instruction
+//   st   sound timer after the instruction
+//   m    memory changed by the instruction as addr:val pairs, comma separated
+//
+// Every field is optional and unknown fields are ignored, a field is only
+// compared when both traces have it. Writes below 0x200 are left out, as the
+// interpreter area (where this interpreter keeps its stack) differs between
+// implementations.
+
+use crate::chip_8::{Chip8, DisplayInterface, KeyboardInterface, SoundInterface};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+pub struct Tracer {
+    out: BufWriter<File>,
+    pc: usize,
+    op: u16,
+    ram: Vec<u8>,
+}
+
+impl Tracer {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# chip-8 trace: pc op i v dt st m")?;
+
+        Ok(Tracer {
+            out,
+            pc: 0,
+            op: 0,
+            ram: Vec::new(),
+        })
+    }
+
+    // call right before Chip8::execute
+    pub fn before<K, D, S>(&mut self, c8: &Chip8<K, D, S>)
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        self.pc = c8.pc();
+        self.op = c8.peek_op();
+        self.ram.clear();
+        self.ram.extend_from_slice(c8.ram());
+    }
+
+    // call right after Chip8::execute
+    pub fn after<K, D, S>(&mut self, c8: &Chip8<K, D, S>) -> io::Result<()>
+    where
+        K: KeyboardInterface,
+        D: DisplayInterface,
+        S: SoundInterface,
+    {
+        let line = TraceLine {
+            pc: Some(self.pc as u16),
+            op: Some(self.op),
+            i: Some(c8.i() as u16),
+            v: Some(*c8.v()),
+            dt: Some(c8.timers().0),
+            st: Some(c8.timers().1),
+            writes: Some(
+                (0x200..c8.ram().len())
+                    .filter(|&addr| c8.ram()[addr] != self.ram[addr])
+                    .map(|addr| (addr as u16, c8.ram()[addr]))
+                    .collect(),
+            ),
+        };
+
+        writeln!(self.out, "{}", line)
+    }
+
+    // writes out what's buffered, which dropping would do without reporting
+    // errors
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[derive(Default, PartialEq)]
+pub struct TraceLine {
+    pc: Option<u16>,
+    op: Option<u16>,
+    i: Option<u16>,
+    v: Option<[u8; 0x10]>,
+    dt: Option<u8>,
+    st: Option<u8>,
+    writes: Option<Vec<(u16, u8)>>,
+}
+
+impl std::fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut fields = Vec::new();
+
+        if let Some(pc) = self.pc {
+            fields.push(format!("pc={:04x}", pc));
+        }
+        if let Some(op) = self.op {
+            fields.push(format!("op={:04x}", op));
+        }
+        if let Some(i) = self.i {
+            fields.push(format!("i={:04x}", i));
+        }
+        if let Some(v) = self.v {
+            let mut s = String::from("v=");
+            for byte in v {
+                write!(s, "{:02x}", byte)?;
+            }
+            fields.push(s);
+        }
+        if let Some(dt) = self.dt {
+            fields.push(format!("dt={:02x}", dt));
+        }
+        if let Some(st) = self.st {
+            fields.push(format!("st={:02x}", st));
+        }
+        if let Some(writes) = &self.writes {
+            let pairs: Vec<String> = writes
+                .iter()
+                .map(|(addr, val)| format!("{:03x}:{:02x}", addr, val))
+                .collect();
+            fields.push(format!("m={}", pairs.join(",")));
+        }
+
+        write!(f, "{}", fields.join(" "))
+    }
+}
+
+impl TraceLine {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut parsed = TraceLine::default();
+
+        for field in line.split_whitespace() {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", field))?;
+
+            match key {
+                "pc" => parsed.pc = Some(parse_hex(value)? as u16),
+                "op" => parsed.op = Some(parse_hex(value)? as u16),
+                "i" => parsed.i = Some(parse_hex(value)? as u16),
+                "dt" => parsed.dt = Some(parse_hex(value)? as u8),
+                "st" => parsed.st = Some(parse_hex(value)? as u8),
+                "v" => {
+                    let digits = value.trim_start_matches("0x");
+                    if digits.len() != 32 {
+                        return Err(format!("expected 32 hex digits for v, got '{}'", value));
+                    }
+                    let mut v = [0; 0x10];
+                    for (n, reg) in v.iter_mut().enumerate() {
+                        *reg = parse_hex(&digits[n * 2..n * 2 + 2])? as u8;
+                    }
+                    parsed.v = Some(v);
+                }
+                "m" => {
+                    let mut writes = Vec::new();
+                    for pair in value.split(',').filter(|pair| !pair.is_empty()) {
+                        let (addr, val) = pair
+                            .split_once(':')
+                            .ok_or_else(|| format!("expected addr:val, got '{}'", pair))?;
+                        let addr = parse_hex(addr)? as u16;
+                        if addr >= 0x200 {
+                            writes.push((addr, parse_hex(val)? as u8));
+                        }
+                    }
+                    writes.sort_unstable();
+                    parsed.writes = Some(writes);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    // names of the fields present in both lines whose values differ
+    fn differences(&self, other: &TraceLine) -> Vec<String> {
+        fn check<T: PartialEq>(name: &str, a: &Option<T>, b: &Option<T>, out: &mut Vec<String>) {
+            if let (Some(a), Some(b)) = (a, b) {
+                if a != b {
+                    out.push(String::from(name));
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+
+        check("pc", &self.pc, &other.pc, &mut out);
+        check("op", &self.op, &other.op, &mut out);
+        check("i", &self.i, &other.i, &mut out);
+        if let (Some(a), Some(b)) = (self.v, other.v) {
+            for n in 0..0x10 {
+                if a[n] != b[n] {
+                    out.push(format!("v[{:x}]", n));
+                }
+            }
+        }
+        check("dt", &self.dt, &other.dt, &mut out);
+        check("st", &self.st, &other.st, &mut out);
+        check("memory", &self.writes, &other.writes, &mut out);
+
+        out
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("expected hex value, got '{}'", s))
+}
+
+pub fn read(path: &Path) -> Result<Vec<TraceLine>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut lines = Vec::new();
+
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed =
+            TraceLine::parse(line).map_err(|e| format!("{}:{}: {}", path.display(), n + 1, e))?;
+        lines.push(parsed);
+    }
+
+    Ok(lines)
+}
+
+// Compares two traces line by line, returns a report of the first divergence
+// with `context` lines before and after it, or None if the traces agree.
+pub fn diff(a: &[TraceLine], b: &[TraceLine], context: usize) -> Option<String> {
+    let common = a.len().min(b.len());
+
+    let (cycle, reason) = match (0..common)
+        .map(|n| (n, a[n].differences(&b[n])))
+        .find(|(_, diffs)| !diffs.is_empty())
+    {
+        Some((n, diffs)) => (n, format!("{} differ", diffs.join(", "))),
+        None if a.len() != b.len() => (
+            common,
+            format!("trace lengths differ ({} vs {} cycles)", a.len(), b.len()),
+        ),
+        None => return None,
+    };
+
+    let mut report = format!("first difference at cycle {}: {}\n", cycle, reason);
+
+    let start = cycle.saturating_sub(context);
+    let end = (cycle + context + 1).min(a.len().max(b.len()));
+
+    for n in start..end {
+        let marker = if n == cycle { '>' } else { ' ' };
+        for (label, trace) in [("a", a), ("b", b)] {
+            let line = match trace.get(n) {
+                Some(line) => line.to_string(),
+                None => String::from("<end of trace>"),
+            };
+            report.push_str(&format!("{} {:>8} {}: {}\n", marker, n, label, line));
+        }
+    }
+
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> Vec<TraceLine> {
+        lines
+            .iter()
+            .map(|line| TraceLine::parse(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_and_prints_a_line() {
+        let line = "pc=0200 op=6a02 i=0000 v=00000000000000000000020000000000 dt=00 st=00 m=";
+        assert_eq!(TraceLine::parse(line).unwrap().to_string(), line);
+
+        // 0x prefixes, unknown fields and the interpreter area are allowed,
+        // writes are sorted
+        let line = TraceLine::parse("pc=0x202 cycles=12 m=300:01,1f0:aa,204:02").unwrap();
+        assert_eq!(line.to_string(), "pc=0202 m=204:02,300:01");
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        assert!(TraceLine::parse("pc").is_err());
+        assert!(TraceLine::parse("pc=zz").is_err());
+        assert!(TraceLine::parse("v=0a00").is_err());
+        assert!(TraceLine::parse("m=300").is_err());
+    }
+
+    #[test]
+    fn diff_finds_the_first_divergence() {
+        let a = parse(&["pc=0200 i=0000", "pc=0202 i=0300", "pc=0204 i=0300"]);
+        let b = parse(&["pc=0200 i=0000", "pc=0202 i=0301", "pc=0206 i=0300"]);
+
+        let report = diff(&a, &b, 1).unwrap();
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some("first difference at cycle 1: i differ"));
+        assert_eq!(lines.count(), 6);
+    }
+
+    #[test]
+    fn diff_compares_fields_both_traces_have() {
+        let a = parse(&["pc=0200 op=6a02", "pc=0202"]);
+        let b = parse(&["pc=0200", "pc=0202 op=00e0"]);
+        assert_eq!(diff(&a, &b, 0), None);
+
+        let b = parse(&["pc=0200"]);
+        let report = diff(&a, &b, 0).unwrap();
+        assert!(
+            report.starts_with("first difference at cycle 1: trace lengths differ (2 vs 1 cycles)")
+        );
+        assert!(report.contains("b: <end of trace>"));
+    }
+}
@@ -0,0 +1,18 @@
+mod common;
+
+use std::path::Path;
+
+#[test]
+fn c_host_uses_the_header() {
+    let library = common::build_library();
+    let include = Path::new(env!("CARGO_MANIFEST_DIR")).join("include");
+
+    let include = format!("-I{}", include.display());
+    let link = format!("-L{}", library.display());
+    let rpath = format!("-Wl,-rpath,{}", library.display());
+    let host = common::compile_host("capi", &[&include, &link, &rpath, "-lchip_8_interpreter"]);
+
+    // the bad opcode is reported through chip8_last_error, not stderr
+    let stderr = common::run_host(&host, &[]);
+    assert_eq!(stderr, "");
+}
@@ -0,0 +1,69 @@
+// Building and running the C test hosts in tests/hosts against the cdylib.
+
+// each test uses only some of these
+#![allow(dead_code)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// target/debug or target/release, where the library is built
+fn target_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+// builds the cdylib, which `cargo test` doesn't, and returns its directory
+pub fn build_library() -> PathBuf {
+    let mut cargo = Command::new(env::var("CARGO").unwrap_or_else(|_| String::from("cargo")));
+    cargo.args(["build", "--lib"]);
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    assert!(
+        cargo.status().unwrap().success(),
+        "building the library failed"
+    );
+
+    target_dir()
+}
+
+// file name of the cdylib on this platform
+pub fn library_name() -> String {
+    format!(
+        "{}chip_8_interpreter{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    )
+}
+
+// compiles tests/hosts/<name>.c with cc and returns the executable
+pub fn compile_host(name: &str, args: &[&str]) -> PathBuf {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = manifest.join("tests/hosts").join(format!("{}.c", name));
+    let output = target_dir().join(format!("{}-host", name));
+
+    let status = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Werror", "-o"])
+        .arg(&output)
+        .arg(&source)
+        .args(args)
+        .status()
+        .expect("running cc");
+    assert!(status.success(), "compiling {} failed", source.display());
+
+    output
+}
+
+// runs a host, which reports what went wrong on stderr, returning stderr
+pub fn run_host(host: &Path, args: &[&Path]) -> String {
+    let output = Command::new(host).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        host.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
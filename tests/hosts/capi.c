@@ -0,0 +1,69 @@
+// Exercises the C API through include/chip8.h: loading, running, save states
+// and a machine that stops on a bad opcode without printing anything.
+
+#include "chip8.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,    \
+                    __LINE__, #cond);                                 \
+            exit(1);                                                  \
+        }                                                             \
+    } while (0)
+
+static int lit(const Chip8Machine *m) {
+    int count = 0;
+    for (size_t y = 0; y < CHIP8_HEIGHT; y++) {
+        for (size_t x = 0; x < CHIP8_WIDTH; x++) {
+            count += chip8_pixel(m, x, y);
+        }
+    }
+    return count;
+}
+
+int main(void) {
+    // sound for 255 frames, then draw the font's 0 and loop
+    const uint8_t beep[] = {0x60, 0xff, 0xf0, 0x18, 0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x0a};
+    const uint8_t crash[] = {0xff, 0xff};
+
+    Chip8Machine *m = chip8_new();
+    CHECK(!chip8_load_rom(m, beep, 0));
+    CHECK(chip8_load_rom(m, beep, sizeof beep));
+    CHECK(chip8_last_error(m) == NULL);
+
+    CHECK(chip8_run_frame(m));
+    CHECK(lit(m) == 14);
+    CHECK(chip8_framebuffer(m)[0] == 0xf);
+    CHECK(chip8_sound_active(m));
+    CHECK(!chip8_pixel(m, CHIP8_WIDTH, 0));
+
+    size_t size = chip8_state_size();
+    uint8_t *state = malloc(size);
+    CHECK(!chip8_save_state(m, state, size - 1));
+    CHECK(chip8_save_state(m, state, size));
+
+    // a bad opcode stops the machine, with the reason kept for the host
+    CHECK(chip8_load_rom(m, crash, sizeof crash));
+    CHECK(!chip8_step(m));
+    CHECK(!chip8_run_frame(m));
+    CHECK(chip8_last_error(m) != NULL);
+    CHECK(strstr(chip8_last_error(m), "bad opcode") != NULL);
+
+    // loading a state gets it going again
+    CHECK(chip8_load_state(m, state, size));
+    CHECK(chip8_last_error(m) == NULL);
+    CHECK(chip8_run_frame(m));
+    CHECK(lit(m) == 14);
+
+    memset(state, 0xff, size);
+    CHECK(!chip8_load_state(m, state, size));
+
+    chip8_free(m);
+    free(state);
+    return 0;
+}
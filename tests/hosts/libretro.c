@@ -0,0 +1,158 @@
+// Minimal libretro frontend: dlopens the core given on the command line and
+// checks loading, running, save states and recovery from a crashed ROM.
+
+#include <dlfcn.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+struct retro_game_info {
+    const char *path;
+    const void *data;
+    size_t size;
+    const char *meta;
+};
+
+static void *core;
+static unsigned pixel_format = ~0u;
+static unsigned frames_drawn, lit, audio_frames, audio_loud;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,    \
+                    __LINE__, #cond);                                 \
+            exit(1);                                                  \
+        }                                                             \
+    } while (0)
+
+static void *sym(const char *name) {
+    void *f = dlsym(core, name);
+    if (!f) {
+        fprintf(stderr, "missing %s\n", name);
+        exit(1);
+    }
+    return f;
+}
+
+#define CALL(type, name) ((type)sym(#name))
+
+static bool environment(unsigned cmd, void *data) {
+    if (cmd == 10) {
+        pixel_format = *(unsigned *)data;
+        return true;
+    }
+    return false;
+}
+
+static void video_refresh(const void *data, unsigned width, unsigned height, size_t pitch) {
+    const uint32_t *pixels = data;
+    CHECK(width == 64 && height == 32 && pitch == 64 * 4);
+
+    frames_drawn++;
+    lit = 0;
+    for (unsigned i = 0; i < width * height; i++) {
+        lit += pixels[i] != 0;
+    }
+}
+
+static size_t audio_sample_batch(const int16_t *data, size_t frames) {
+    audio_frames = frames;
+    audio_loud = 0;
+    for (size_t i = 0; i < frames * 2; i++) {
+        audio_loud += data[i] != 0;
+    }
+    return frames;
+}
+
+static void input_poll(void) {}
+
+static int16_t input_state(unsigned port, unsigned device, unsigned index, unsigned id) {
+    return 0;
+}
+
+static bool load(const uint8_t *rom, size_t size) {
+    struct retro_game_info game = {"test.ch8", rom, size, NULL};
+    return CALL(bool (*)(const struct retro_game_info *), retro_load_game)(&game);
+}
+
+static void run(int frames) {
+    for (int i = 0; i < frames; i++) {
+        CALL(void (*)(void), retro_run)();
+    }
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    core = dlopen(argv[1], RTLD_NOW);
+    if (!core) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+
+    CHECK(CALL(unsigned (*)(void), retro_api_version)() == 1);
+    CALL(void (*)(void *), retro_set_environment)(environment);
+    CALL(void (*)(void *), retro_set_video_refresh)(video_refresh);
+    CALL(void (*)(void *), retro_set_audio_sample_batch)(audio_sample_batch);
+    CALL(void (*)(void *), retro_set_input_poll)(input_poll);
+    CALL(void (*)(void *), retro_set_input_state)(input_state);
+    CALL(void (*)(void), retro_init)();
+
+    // sound for 255 frames, then draw the font's 0 and loop
+    const uint8_t beep[] = {0x60, 0xff, 0xf0, 0x18, 0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x0a};
+    CHECK(!load(beep, 0));
+    CHECK(load(beep, sizeof beep));
+    CHECK(pixel_format == 1);
+
+    run(1);
+    CHECK(frames_drawn == 1);
+    CHECK(lit == 14);
+    CHECK(audio_frames == 735);
+    CHECK(audio_loud > 0);
+
+    size_t size = CALL(size_t (*)(void), retro_serialize_size)();
+    uint8_t *state = malloc(size);
+    CHECK(CALL(bool (*)(void *, size_t), retro_serialize)(state, size));
+    CHECK(!CALL(bool (*)(void *, size_t), retro_serialize)(state, size - 1));
+
+    // a bad opcode freezes the screen instead of aborting the frontend
+    const uint8_t crash[] = {0x00, 0x00};
+    CHECK(load(crash, sizeof crash));
+    run(3);
+    CHECK(frames_drawn == 4);
+    CHECK(lit == 0);
+    CHECK(audio_loud == 0);
+
+    // loading a state brings it back
+    CHECK(CALL(bool (*)(const void *, size_t), retro_unserialize)(state, size));
+    run(1);
+    CHECK(lit == 14);
+    CHECK(audio_loud > 0);
+
+    // as does a reset, which clears the screen before drawing it again
+    const uint8_t draw_then_crash[] = {0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x00, 0x00};
+    CHECK(load(draw_then_crash, sizeof draw_then_crash));
+    run(2);
+    CHECK(lit == 14);
+    CALL(void (*)(void), retro_reset)();
+    run(1);
+    CHECK(lit == 14);
+
+    // a state that isn't one is refused
+    memset(state, 0xff, size);
+    CHECK(!CALL(bool (*)(const void *, size_t), retro_unserialize)(state, size));
+
+    // drawing from the end of memory wraps around, then the 0 is drawn
+    const uint8_t edge[] = {0xaf, 0xff, 0xd0, 0x0f, 0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x0a};
+    CHECK(load(edge, sizeof edge));
+    run(2);
+    CHECK(lit == 14);
+
+    CALL(void (*)(void), retro_unload_game)();
+    CALL(void (*)(void), retro_deinit)();
+    free(state);
+    return 0;
+}
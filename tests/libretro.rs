@@ -0,0 +1,9 @@
+mod common;
+
+#[test]
+fn libretro_host_runs_the_core() {
+    let library = common::build_library().join(common::library_name());
+    let host = common::compile_host("libretro", &["-ldl"]);
+
+    common::run_host(&host, &[&library]);
+}